@@ -25,6 +25,29 @@ pub fn lcm(a: i64, b: i64) -> i64 {
     a * b / gcd(a, b)
 }
 
+/// Returns `(g, x, y)` such that `a * x + b * y = g = gcd(a, b)`
+pub fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+/// Solves the system `t = r_i (mod m_i)` for moduli that need not be coprime. Returns `Some((t, m))` where `t` is the
+/// smallest non-negative solution and `m` the lcm of all moduli, or `None` if the congruences are incompatible.
+pub fn chinese_remainder(congruences: &[(i128, i128)]) -> Option<(i128, i128)> {
+    congruences.iter().try_fold((0, 1), |(r1, m1), &(r2, m2)| {
+        let (g, p, _) = extended_gcd(m1, m2);
+        if (r2 - r1) % g != 0 {
+            return None;
+        }
+        let m = m1 / g * m2;
+        let t = r1 + m1 * ((r2 - r1) / g * p % (m2 / g));
+        Some((t.rem_euclid(m), m))
+    })
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum Direction { North, East, South, West }
@@ -65,7 +88,7 @@ pub fn next_coord<T: Num + PartialOrd + Clone>((x, y): (T, T), direction: Direct
 
 #[cfg(test)]
 mod tests {
-    use crate::common::{gcd, lcm};
+    use crate::common::{chinese_remainder, gcd, lcm};
 
     #[test]
     fn should_correctly_compute_gcd() {
@@ -84,4 +107,12 @@ mod tests {
         assert_eq!(lcm(8, 12), 24);
         assert_eq!(lcm(49, 13), 49 * 13);
     }
+
+    #[test]
+    fn should_solve_system_of_congruences() {
+        assert_eq!(chinese_remainder(&[(2, 3), (3, 5), (2, 7)]), Some((23, 105)));
+        assert_eq!(chinese_remainder(&[(2, 6), (4, 8)]), Some((20, 24)));
+        assert_eq!(chinese_remainder(&[(1, 6), (2, 4)]), None);
+        assert_eq!(chinese_remainder(&[]), Some((0, 1)));
+    }
 }
@@ -2,10 +2,9 @@ use std::collections::HashMap;
 use std::fs;
 
 use lazy_static::lazy_static;
-use regex::Regex;
 
 lazy_static! {
-    static ref DIGITS: HashMap<&'static str, i32> = HashMap::from([
+    static ref DIGITS: HashMap<&'static str, u32> = HashMap::from([
         ("one", 1),
         ("two", 2),
         ("three", 3),
@@ -26,57 +25,120 @@ pub fn main() {
     println!("part2: Sum of numbers={}", part_2(&contents));
 }
 
-fn part_1(contents: &String) -> i64 {
-    let mut sum = 0;
-    let regex = Regex::new(r"\d").unwrap();
+fn part_1(contents: &str) -> i64 {
+    CalibrationScanner::digits_only().sum(contents).unwrap()
+}
 
-    for line in contents.lines() {
-        let digits: Vec<String> = regex.find_iter(line)
-            .map(|d| d.as_str().to_string())
-            .collect();
+fn part_2(contents: &str) -> i64 {
+    CalibrationScanner::english().sum(contents).unwrap()
+}
 
-        let number: i64 = format!("{}{}", digits[0], digits.last().unwrap())
-            .parse().unwrap();
-        sum += number;
-    }
-    sum
+/// Finds digits in a line of calibration text, both as plain ascii digits and as any of the words in its
+/// vocabulary. Words may overlap, e.g. "twoneight" contains a 2, a 1 and an 8.
+pub struct CalibrationScanner {
+    vocabulary: Vec<(String, u32)>,
 }
 
-fn part_2(contents: &String) -> i64 {
-    let digits_string = DIGITS.keys().fold(String::new(), |mut acc, elt| {
-        if !acc.is_empty() { acc.push_str("|"); }
-        acc.push_str(elt);
-        acc
-    });
+impl CalibrationScanner {
+    pub fn digits_only() -> CalibrationScanner {
+        CalibrationScanner { vocabulary: vec![] }
+    }
+
+    pub fn english() -> CalibrationScanner {
+        CalibrationScanner::digits_only().with_words(DIGITS.iter().map(|(word, digit)| (*word, *digit))).unwrap()
+    }
 
-    let regex = Regex::new(format!("\\d|{}", digits_string).as_str()).unwrap();
-    let reversed_regex = Regex::new(format!("\\d|{}", reverse(&digits_string)).as_str()).unwrap();
+    /// Adds the given words to the vocabulary, e.g. `("zero", 0)` or `("drei", 3)`. Every word has to stand for a
+    /// single digit, since calibration values are made of two digits.
+    pub fn with_words<'w>(mut self, words: impl IntoIterator<Item=(&'w str, u32)>) -> Result<CalibrationScanner, String> {
+        for (word, digit) in words.into_iter().filter(|(word, _)| !word.is_empty()) {
+            if digit > 9 {
+                return Err(format!("'{word}' stands for {digit}, which is not a single digit"));
+            }
+            self.vocabulary.push((word.to_string(), digit));
+        }
+        Ok(self)
+    }
 
-    let mut sum = 0;
-    for line in contents.lines() {
-        let first_digit_string = regex.find(line).unwrap().as_str().to_string();
-        let first = to_digit_str(first_digit_string);
+    /// Returns every digit found in the line as `(byte_position, digit)`, ordered by position.
+    pub fn find_digits(&self, line: &str) -> Vec<(usize, u32)> {
+        line.char_indices()
+            .filter_map(|(idx, c)| {
+                c.to_digit(10).or_else(|| {
+                    // When several words match, e.g. "eight" and "eighteen", the longest one is meant
+                    self.vocabulary.iter()
+                        .filter(|(word, _)| line[idx..].starts_with(word.as_str()))
+                        .max_by_key(|(word, _)| word.len())
+                        .map(|(_, digit)| *digit)
+                }).map(|digit| (idx, digit))
+            })
+            .collect()
+    }
 
-        let line_reversed = reverse(&line).as_str().to_owned();
-        let last_digit_string = reverse(reversed_regex.find(&line_reversed).unwrap().as_str());
-        let last = to_digit_str(last_digit_string);
+    pub fn calibration_value(&self, line: &str) -> Result<i64, String> {
+        let digits = self.find_digits(line);
+        match (digits.first(), digits.last()) {
+            (Some((_, first)), Some((_, last))) => Ok((first * 10 + last) as i64),
+            _ => Err(format!("No digit found in line '{line}'")),
+        }
+    }
 
-        let number: i64 = format!("{}{}", first, last)
-            .parse().unwrap();
-        sum += number;
+    pub fn sum(&self, contents: &str) -> Result<i64, String> {
+        contents.lines()
+            .enumerate()
+            .map(|(idx, line)| self.calibration_value(line).map_err(|err| format!("Line {}: {err}", idx + 1)))
+            .sum()
     }
-    sum
 }
 
-fn reverse(string: &str) -> String {
-    string.chars().rev().collect()
-}
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::day01::{CalibrationScanner, part_1, part_2};
+
+    #[test]
+    fn should_sum_calibration_values_of_example_1() {
+        let contents = fs::read_to_string("./input_1_example1.txt").unwrap();
+        assert_eq!(part_1(&contents), 142);
+    }
+
+    #[test]
+    fn should_sum_calibration_values_of_example_2() {
+        let contents = fs::read_to_string("./input_1_example2.txt").unwrap();
+        assert_eq!(part_2(&contents), 18 + 281);
+    }
+
+    #[test]
+    fn should_find_overlapping_words() {
+        let digits = CalibrationScanner::english().find_digits("twoneight");
+        assert_eq!(digits, vec![(0, 2), (2, 1), (4, 8)]);
+    }
+
+    #[test]
+    fn should_support_other_vocabularies() {
+        let scanner = CalibrationScanner::digits_only().with_words([("zero", 0), ("eins", 1), ("drei", 3)]).unwrap();
+        assert_eq!(scanner.calibration_value("dreizeroxeins"), Ok(31));
+        assert_eq!(scanner.calibration_value("zero7"), Ok(7));
+    }
+
+    #[test]
+    fn should_report_lines_without_digits() {
+        let result = CalibrationScanner::english().sum("1two\nnothing here\n3");
+        assert_eq!(result, Err("Line 2: No digit found in line 'nothing here'".to_string()));
+    }
+
+    #[test]
+    fn should_reject_words_that_are_not_a_single_digit() {
+        let scanner = CalibrationScanner::digits_only().with_words([("one", 1), ("ten", 10)]);
+        assert_eq!(scanner.err(), Some("'ten' stands for 10, which is not a single digit".to_string()));
+    }
 
-fn to_digit_str(input: String) -> String {
-    if Regex::new(r"\d").unwrap().is_match(&input) {
-        input
-    } else {
-        let digit = DIGITS.get(input.as_str()).unwrap();
-        format!("{digit}")
+    #[test]
+    fn should_prefer_longest_matching_word() {
+        let scanner = CalibrationScanner::digits_only().with_words([("sech", 6), ("sechs", 7)]).unwrap();
+        assert_eq!(scanner.find_digits("sechsx"), vec![(0, 7)]);
+        let scanner = CalibrationScanner::digits_only().with_words([("sechs", 7), ("sech", 6)]).unwrap();
+        assert_eq!(scanner.find_digits("sechsx"), vec![(0, 7)]);
     }
 }
@@ -1,13 +1,15 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use lazy_static::lazy_static;
 use regex::Regex;
+use crate::common::split_first;
 
 pub fn main() {
     let content = fs::read_to_string("./inputs/day02/input.txt")
         .expect("Should be able to read the file");
     let games: Vec<Game> = content.lines().map(|line| read(line)).collect();
 
-    let max = Subset { red: 12, green: 13, blue: 14, };
+    let max = Subset::rgb(12, 13, 14);
     part1(&games, &max);
     part2(&games);
 }
@@ -29,17 +31,18 @@ fn part2(games: &Vec<Game>) {
     println!("Sum of power of the minimum required sets is {sum}")
 }
 
+const CUBE_COLORS: [&str; 3] = ["red", "green", "blue"];
+
 #[derive(Eq, PartialEq, Debug)]
 struct Game {
     id: i32,
     subsets: Vec<Subset>,
 }
 
-#[derive(Eq, PartialEq, Debug)]
+/// The cubes revealed in a single round, by color. Colors that were not revealed are not stored, so their count is 0.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
 struct Subset {
-    red: i32,
-    blue: i32,
-    green: i32,
+    counts: BTreeMap<String, i32>,
 }
 
 impl Game {
@@ -49,52 +52,143 @@ impl Game {
             subsets: Vec::new(),
         }
     }
+
+    /// All colors that were revealed in any of the rounds
+    pub fn colors(&self) -> BTreeSet<&str> {
+        self.subsets.iter().flat_map(|subset| subset.counts.keys().map(|color| color.as_str())).collect()
+    }
+}
+
+impl Subset {
+    pub fn rgb(red: i32, green: i32, blue: i32) -> Self {
+        Subset::from_counts(CUBE_COLORS.iter().copied().zip([red, green, blue]))
+    }
+
+    pub fn from_counts<'c>(counts: impl IntoIterator<Item=(&'c str, i32)>) -> Self {
+        let mut subset = Subset::default();
+        for (color, count) in counts {
+            subset.set(color, count);
+        }
+        subset
+    }
+
+    pub fn count(&self, color: &str) -> i32 {
+        *self.counts.get(color).unwrap_or(&0)
+    }
+
+    pub fn set(&mut self, color: &str, count: i32) {
+        if count == 0 {
+            self.counts.remove(color);
+        } else {
+            self.counts.insert(color.to_string(), count);
+        }
+    }
+
+    pub fn total(&self) -> i32 {
+        self.counts.values().sum()
+    }
+
+    /// Whether every color in this subset is available in at least the same amount in `max`
+    pub fn fits_in(&self, max: &Subset) -> bool {
+        self.counts.iter().all(|(color, count)| *count <= max.count(color))
+    }
 }
 
 lazy_static! {
     static ref GAME_ID_REGEX: Regex = Regex::new(r"Game (\d+):.*").unwrap();
-    static ref BLUE_REGEX: Regex =  Regex::new(r"\s(\d+) blue").unwrap();
-    static ref RED_REGEX: Regex =   Regex::new(r"\s(\d+) red").unwrap();
-    static ref GREEN_REGEX: Regex = Regex::new(r"\s(\d+) green").unwrap();
+    static ref CUBES_REGEX: Regex = Regex::new(r"(\d+) (\w+)").unwrap();
 }
 
 pub fn read(game_string: &str) -> Game {
     let extracted: i32 = GAME_ID_REGEX.captures(game_string).unwrap()[1]
         .parse().unwrap();
     let mut game = Game::new(extracted);
-    game_string.split(";").fold(&mut game, |game: &mut Game, subset_string| {
-        let r: i32 = RED_REGEX.captures(subset_string).map_or(0, |x| x[1].parse().unwrap());
-        let g: i32 = GREEN_REGEX.captures(subset_string).map_or(0, |x| x[1].parse().unwrap());
-        let b: i32 = BLUE_REGEX.captures(subset_string).map_or(0, |x| x[1].parse().unwrap());
-        game.subsets.push(Subset { red: r, blue: b, green: g });
+    let (_, rounds_string) = split_first(game_string, ':').expect("Should contain ':'");
+    rounds_string.split(';').fold(&mut game, |game: &mut Game, subset_string| {
+        let counts = CUBES_REGEX.captures_iter(subset_string)
+            .map(|x| (x.get(2).unwrap().as_str(), x[1].parse().unwrap()));
+        game.subsets.push(Subset::from_counts(counts));
         game
     });
     game
 }
 
 pub fn is_valid(game: &Game, max: &Subset) -> bool {
-    game.subsets.iter().all(|subset|
-        subset.red <= max.red
-            && subset.green <= max.green
-            && subset.blue <= max.blue
-    )
+    first_invalid_round(game, max).is_none()
+}
+
+/// Index of the first round that reveals more cubes of some color than `max` contains
+fn first_invalid_round(game: &Game, max: &Subset) -> Option<usize> {
+    game.subsets.iter().position(|subset| !subset.fits_in(max))
 }
 
 pub fn fewest_necessary(game: &Game) -> Subset {
-    Subset {
-        red: game.subsets.iter().max_by_key(|subset| subset.red).unwrap().red,
-        green: game.subsets.iter().max_by_key(|subset| subset.green).unwrap().green,
-        blue: game.subsets.iter().max_by_key(|subset| subset.blue).unwrap().blue, }
+    fewest_necessary_for(game, &game.colors().into_iter().collect::<Vec<_>>())
+}
+
+/// The smallest bag, restricted to the given colors, that could have produced every round of the game
+fn fewest_necessary_for(game: &Game, colors: &[&str]) -> Subset {
+    Subset::from_counts(colors.iter().map(|color| {
+        (*color, game.subsets.iter().map(|subset| subset.count(color)).max().unwrap_or(0))
+    }))
 }
 
+/// The puzzle's power: the product of the red, green and blue counts. Other colors are ignored, and a subset without
+/// one of these three has power 0.
 pub fn power(subset: &Subset) -> i32 {
-    subset.red * subset.green * subset.blue
+    CUBE_COLORS.iter().map(|color| subset.count(color)).product()
+}
+
+/// The bag with exactly `bag_size` cubes that maximizes the likelihood of the observed rounds, assuming every round
+/// draws its cubes at random from the bag (without replacement) and puts them back afterwards. Returns `None` if no
+/// bag of that size can produce all rounds.
+fn most_likely_bag(game: &Game, bag_size: i32) -> Option<Subset> {
+    let minimum = fewest_necessary(game);
+    let colors: Vec<&str> = minimum.counts.keys().map(|color| color.as_str()).collect();
+    if colors.is_empty() || minimum.total() > bag_size {
+        return None;
+    }
+
+    let mut best: Option<(f64, Vec<i32>)> = None;
+    let mut composition = vec![0; colors.len()];
+    for_each_composition(&colors, &minimum, bag_size, 0, &mut composition, &mut |candidate| {
+        let log_likelihood: f64 = game.subsets.iter().map(|subset| {
+            colors.iter().zip(candidate).map(|(color, available)| ln_binomial(*available, subset.count(color))).sum::<f64>()
+        }).sum();
+        if best.as_ref().is_none_or(|(best_log_likelihood, _)| log_likelihood > *best_log_likelihood) {
+            best = Some((log_likelihood, candidate.clone()));
+        }
+    });
+
+    best.map(|(_, counts)| Subset::from_counts(colors.iter().copied().zip(counts)))
+}
+
+/// Calls `action` for every way of dividing `remaining` cubes over `colors[idx..]`, where each color gets at least
+/// its count in `minimum`
+fn for_each_composition(colors: &[&str], minimum: &Subset, remaining: i32, idx: usize, composition: &mut Vec<i32>, action: &mut impl FnMut(&Vec<i32>)) {
+    let min_count = minimum.count(colors[idx]);
+    if idx == colors.len() - 1 {
+        if remaining >= min_count {
+            composition[idx] = remaining;
+            action(composition);
+        }
+        return;
+    }
+    let min_rest: i32 = colors[idx + 1..].iter().map(|color| minimum.count(color)).sum();
+    for count in min_count..=(remaining - min_rest) {
+        composition[idx] = count;
+        for_each_composition(colors, minimum, remaining - count, idx + 1, composition, action);
+    }
+}
+
+fn ln_binomial(n: i32, k: i32) -> f64 {
+    (0..k).map(|i| ((n - i) as f64).ln() - ((i + 1) as f64).ln()).sum()
 }
 
 #[cfg(test)]
 mod tests {
     use crate::day02;
-    use crate::day02::{fewest_necessary, Game, is_valid, power, Subset};
+    use crate::day02::{fewest_necessary, fewest_necessary_for, first_invalid_round, Game, is_valid, most_likely_bag, power, Subset};
 
     const SINGLE_ROUND_GAME_STR: &str = "Game 32: 3 blue, 4 red, 27 green";
     const SAMPLE_GAME_STR: &str = "Game 7: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green";
@@ -103,9 +197,9 @@ mod tests {
         Game {
             id: 7,
             subsets: vec![
-                Subset { red: 4, green: 0, blue: 3 },
-                Subset { red: 1, green: 2, blue: 6 },
-                Subset { red: 0, green: 2, blue: 0 },
+                Subset::rgb(4, 0, 3),
+                Subset::rgb(1, 2, 6),
+                Subset::rgb(0, 2, 0),
             ],
         }
     }
@@ -119,19 +213,19 @@ mod tests {
     #[test]
     fn should_correctly_extract_red_from_a_single_round_game() {
         let game = day02::read(SINGLE_ROUND_GAME_STR);
-        assert_eq!(game.subsets[0].red, 4);
+        assert_eq!(game.subsets[0].count("red"), 4);
     }
 
     #[test]
     fn subset_should_have_count_0_for_red_if_it_is_not_mentioned_in_the_string() {
         let game = day02::read("Game 32: 3 blue, 27 green");
-        assert_eq!(game.subsets[0].red, 0);
+        assert_eq!(game.subsets[0].count("red"), 0);
     }
 
     #[test]
     fn should_extract_a_subset_from_a_single_round_game() {
         let game = day02::read(SINGLE_ROUND_GAME_STR);
-        assert_eq!(game.subsets[0], Subset { red: 4, green: 27, blue: 3 });
+        assert_eq!(game.subsets[0], Subset::rgb(4, 27, 3));
     }
 
     #[test]
@@ -148,32 +242,32 @@ mod tests {
 
     #[test]
     fn when_all_below_maximum_game_should_be_valid() {
-        let valid = is_valid(&sample_game(), &Subset { red: 4, green: 2, blue: 6,  });
+        let valid = is_valid(&sample_game(), &Subset::rgb(4, 2, 6));
         assert!(valid, "Game wasn't valid!")
     }
 
     #[test]
     fn when_red_is_too_large_should_not_be_valid() {
-        let valid = is_valid(&sample_game(), &Subset { red: 3, green: 2, blue: 6, });
+        let valid = is_valid(&sample_game(), &Subset::rgb(3, 2, 6));
         assert!(!valid, "Game was valid!")
     }
 
     #[test]
     fn when_green_is_too_large_should_not_be_valid() {
-        let valid = is_valid(&sample_game(), &Subset { red: 4, green: 1, blue: 6, });
+        let valid = is_valid(&sample_game(), &Subset::rgb(4, 1, 6));
         assert!(!valid, "Game was valid!")
     }
 
     #[test]
     fn when_blue_is_too_large_should_not_be_valid() {
-        let valid = is_valid(&sample_game(), &Subset { red: 4, green: 2, blue: 5, });
+        let valid = is_valid(&sample_game(), &Subset::rgb(4, 2, 5));
         assert!(!valid, "Game was valid!")
     }
 
     #[test]
     fn fewest_necessary_should_return_expected_minima() {
         let minima = fewest_necessary(&sample_game());
-        assert_eq!(minima, Subset { red: 4, green: 2, blue: 6, });
+        assert_eq!(minima, Subset::rgb(4, 2, 6));
     }
 
     #[test]
@@ -181,4 +275,42 @@ mod tests {
         let power = power(&fewest_necessary(&sample_game()));
         assert_eq!(power, 48);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_parse_arbitrary_colors() {
+        let game = day02::read("Game 3: 2 purple, 1 red; 5 yellow");
+        assert_eq!(game.subsets[0], Subset::from_counts([("purple", 2), ("red", 1)]));
+        assert_eq!(game.subsets[1].count("yellow"), 5);
+        assert_eq!(game.colors().into_iter().collect::<Vec<_>>(), vec!["purple", "red", "yellow"]);
+    }
+
+    #[test]
+    fn should_report_the_round_that_made_a_game_invalid() {
+        assert_eq!(first_invalid_round(&sample_game(), &Subset::rgb(4, 2, 5)), Some(1));
+        assert_eq!(first_invalid_round(&sample_game(), &Subset::rgb(4, 1, 6)), Some(1));
+        assert_eq!(first_invalid_round(&sample_game(), &Subset::rgb(4, 2, 6)), None);
+    }
+
+    #[test]
+    fn fewest_necessary_should_only_consider_given_colors() {
+        let minima = fewest_necessary_for(&sample_game(), &["red", "blue"]);
+        assert_eq!(minima, Subset::from_counts([("red", 4), ("blue", 6)]));
+    }
+
+    #[test]
+    fn most_likely_bag_should_respect_minima_and_bag_size() {
+        let game = day02::read("Game 1: 2 red; 2 red; 2 red, 1 blue");
+        assert_eq!(most_likely_bag(&game, 2), None);
+        assert_eq!(most_likely_bag(&game, 3), Some(Subset::from_counts([("red", 2), ("blue", 1)])));
+        let bag = most_likely_bag(&game, 10).unwrap();
+        assert_eq!(bag.total(), 10);
+        assert!(bag.count("red") > bag.count("blue"), "Expected mostly red cubes, got {:?}", bag);
+    }
+
+    #[test]
+    fn power_should_only_multiply_red_green_and_blue() {
+        let game = day02::read("Game 1: 2 red, 3 purple; 4 green, 5 blue");
+        assert_eq!(power(&fewest_necessary(&game)), 2 * 4 * 5);
+        assert_eq!(power(&Subset::from_counts([("red", 2), ("purple", 3)])), 0);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 
 use lazy_static::lazy_static;
@@ -7,12 +8,11 @@ pub fn main() {
     println!("day03");
 
     let schematic = Schematic::new(&fs::read_to_string("./inputs/day03/input.txt").unwrap());
-    let all_numbers = possible_part_numbers(&schematic);
-    let part_numbers: Vec<&Number> = all_numbers.iter()
-        .filter(|number| is_part_number(&schematic, number))
+    let part_numbers: Vec<&Number> = schematic.numbers.iter()
+        .filter(|number| !schematic.symbols_adjacent_to(number).is_empty())
         .collect();
     part1(&part_numbers);
-    part2(&schematic, &part_numbers)
+    part2(&schematic)
 }
 
 fn part1(part_numbers: &Vec<&Number>) {
@@ -20,27 +20,10 @@ fn part1(part_numbers: &Vec<&Number>) {
     println!("Sum is {sum}")
 }
 
-fn part2(schematic: &Schematic, part_numbers: &Vec<&Number>) {
-    let gear_ratios = schematic.content.iter().enumerate()
-        .flat_map(|(y, row)| {
-            row.iter().enumerate().filter_map(move |(x, c)| {
-                if *c == '*' {
-                    let neighboring_numbers: Vec<&Number> = part_numbers.iter()
-                        .filter(|number| is_neighbor(x as i32, y as i32, number))
-                        .map(|refref_number| *refref_number)
-                        .collect();
-                    if neighboring_numbers.len() == 2 {
-                        let ratio = neighboring_numbers[0].value * neighboring_numbers[1].value;
-                        Some(ratio)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
-        });
-    let sum: i32 = gear_ratios.sum();
+fn part2(schematic: &Schematic) {
+    let sum: i32 = schematic.gears('*', 2).iter()
+        .map(|(_, numbers)| numbers.iter().map(|number| number.value).product::<i32>())
+        .sum();
     println!("Sum of gear ratios is {sum}")
 }
 
@@ -50,9 +33,13 @@ struct Schematic {
     content: Vec<Vec<char>>,
     height: usize,
     width: usize,
+    numbers: Vec<Number>,
+    symbols: Vec<Symbol>,
+    /// For each cell the index into `numbers` of the number covering it, if any
+    number_at: Vec<Vec<Option<usize>>>,
 }
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Clone)]
 struct Number {
     x: usize,
     y: usize,
@@ -60,6 +47,13 @@ struct Number {
     value: i32,
 }
 
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+struct Symbol {
+    x: usize,
+    y: usize,
+    kind: char,
+}
+
 lazy_static! {
     static ref NUMBER_REGEX: Regex = Regex::new(r"\d+").unwrap();
     static ref NON_SYMBOL_REGEX: Regex = Regex::new(r"\.|\d").unwrap();
@@ -67,21 +61,83 @@ lazy_static! {
 
 impl Schematic {
     pub fn new(schematic_string: &str) -> Self {
-        Self {
-            height: schematic_string.lines().count(),
-            width: schematic_string.lines().nth(0).unwrap().len(),
-            original: schematic_string.to_string(),
-            content: schematic_string.lines().map(|line| line.chars().collect::<Vec<char>>()).collect(),
+        let content: Vec<Vec<char>> = schematic_string.lines().map(|line| line.chars().collect::<Vec<char>>()).collect();
+        let height = content.len();
+        let width = schematic_string.lines().nth(0).unwrap().len();
+
+        let numbers = extract_numbers(schematic_string);
+        let mut number_at = vec![vec![None; width]; height];
+        for (idx, number) in numbers.iter().enumerate() {
+            number_at[number.y][number.x..number.x + number.length].fill(Some(idx));
         }
+
+        let symbols = content.iter().enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate()
+                .filter(|(_, c)| is_symbol(**c))
+                .map(move |(x, c)| Symbol { x, y, kind: *c }))
+            .collect();
+
+        Self { original: schematic_string.to_string(), content, height, width, numbers, symbols, number_at }
+    }
+
+    /// All numbers occupying one of the 8 cells around (x, y), each reported once
+    pub fn numbers_adjacent_to(&self, x: usize, y: usize) -> Vec<&Number> {
+        let mut indices: Vec<usize> = self.neighbors(x, y, 1, 1)
+            .filter_map(|(nx, ny)| self.number_at[ny][nx])
+            .collect();
+        indices.sort();
+        indices.dedup();
+        indices.into_iter().map(|idx| &self.numbers[idx]).collect()
+    }
+
+    pub fn symbols_adjacent_to(&self, number: &Number) -> Vec<Symbol> {
+        self.neighbors(number.x, number.y, number.length, 1)
+            .filter(|(nx, ny)| is_symbol(self.content[*ny][*nx]))
+            .map(|(nx, ny)| Symbol { x: nx, y: ny, kind: self.content[ny][nx] })
+            .collect()
+    }
+
+    pub fn symbols_by_kind(&self) -> BTreeMap<char, Vec<&Symbol>> {
+        self.symbols.iter().fold(BTreeMap::new(), |mut acc, symbol| {
+            acc.entry(symbol.kind).or_insert_with(Vec::new).push(symbol);
+            acc
+        })
+    }
+
+    /// All symbols of the given kind that are adjacent to exactly `num_neighbors` numbers, together with those numbers
+    pub fn gears(&self, kind: char, num_neighbors: usize) -> Vec<(&Symbol, Vec<&Number>)> {
+        self.symbols.iter()
+            .filter(|symbol| symbol.kind == kind)
+            .map(|symbol| (symbol, self.numbers_adjacent_to(symbol.x, symbol.y)))
+            .filter(|(_, numbers)| numbers.len() == num_neighbors)
+            .collect()
+    }
+
+    /// Coordinates within the schematic surrounding the `width` x `height` block with top-left corner (x, y)
+    fn neighbors(&self, x: usize, y: usize, width: usize, height: usize) -> impl Iterator<Item=(usize, usize)> + '_ {
+        let (x, y) = (x as i64, y as i64);
+        let (width, height) = (width as i64, height as i64);
+        (y - 1..=y + height).flat_map(move |ny| (x - 1..=x + width).map(move |nx| (nx, ny)))
+            .filter(move |(nx, ny)| !(x <= *nx && *nx < x + width && y <= *ny && *ny < y + height))
+            .filter(|(nx, ny)| 0 <= *nx && *nx < self.width as i64 && 0 <= *ny && *ny < self.height as i64)
+            .map(|(nx, ny)| (nx as usize, ny as usize))
     }
 }
 
+fn is_symbol(c: char) -> bool {
+    !c.is_ascii_digit() && c != '.'
+}
+
 pub fn possible_part_numbers(schematic: &Schematic) -> Vec<Number> {
-    schematic.original.lines().enumerate().flat_map(move |(y, line)| {
+    schematic.numbers.clone()
+}
+
+fn extract_numbers(schematic_string: &str) -> Vec<Number> {
+    schematic_string.lines().enumerate().flat_map(move |(y, line)| {
         NUMBER_REGEX.captures_iter(line).map(move |number_str| {
             Number {
                 x: number_str.get(0).unwrap().start(),
-                y: y,
+                y,
                 length: number_str[0].len(),
                 value: number_str[0].parse().unwrap(),
             }
@@ -90,28 +146,7 @@ pub fn possible_part_numbers(schematic: &Schematic) -> Vec<Number> {
 }
 
 pub fn is_part_number(schematic: &Schematic, number: &Number) -> bool {
-    // println!("Checking {:?}", number);
-    let x = number.x as i32;
-    let y = number.y as i32;
-    let length = number.length as i32;
-    let mut neighbors = vec![
-        (x - 1, y), (x + length, y),
-    ];
-    for x_offset in -1..length + 1 {
-        neighbors.push((x + x_offset, y + 1));
-        neighbors.push((x + x_offset, y - 1));
-    }
-    neighbors.iter().any(|(x, y)| {
-        let x = *x;
-        let y = *y;
-        if 0 <= x && x < schematic.width as i32 && 0 <= y && y < schematic.height as i32 {
-            let char = schematic.content[y as usize][x as usize];
-            // println!("Chart at (x,y)=({x},{y}) is '{char}'");
-            !char.is_digit(10) && char != '.'
-        } else {
-            false
-        }
-    })
+    !schematic.symbols_adjacent_to(number).is_empty()
 }
 
 pub fn is_neighbor(x: i32, y: i32, number: &Number) -> bool {
@@ -125,7 +160,7 @@ pub fn is_neighbor(x: i32, y: i32, number: &Number) -> bool {
 mod tests {
     use std::fs;
 
-    use crate::day03::{is_neighbor, is_part_number, Number, possible_part_numbers, Schematic};
+    use crate::day03::{is_neighbor, is_part_number, Number, possible_part_numbers, Schematic, Symbol};
 
     fn load_example() -> Schematic {
         Schematic::new(&fs::read_to_string("./inputs/day03/input_example.txt").unwrap())
@@ -189,4 +224,39 @@ mod tests {
         assert!(!is_neighbor(3, 2, &numbers[0]));
         assert!(!is_neighbor(4, 1, &numbers[0]));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_find_numbers_adjacent_to_a_symbol() {
+        let schematic = load_example();
+        let values: Vec<i32> = schematic.numbers_adjacent_to(3, 1).iter().map(|number| number.value).collect();
+        assert_eq!(values, vec![467, 35]);
+        assert!(schematic.numbers_adjacent_to(9, 9).is_empty());
+    }
+
+    #[test]
+    fn should_find_symbols_adjacent_to_a_number() {
+        let schematic = load_example();
+        let numbers = possible_part_numbers(&schematic);
+
+        assert_eq!(schematic.symbols_adjacent_to(&numbers[4]), vec![Symbol { x: 3, y: 4, kind: '*' }]);
+        assert!(schematic.symbols_adjacent_to(&numbers[1]).is_empty());
+    }
+
+    #[test]
+    fn should_group_symbols_by_kind() {
+        let schematic = load_example();
+        let by_kind = schematic.symbols_by_kind();
+        assert_eq!(by_kind.keys().copied().collect::<Vec<_>>(), vec!['#', '$', '*', '+']);
+        assert_eq!(by_kind[&'*'].len(), 3);
+    }
+
+    #[test]
+    fn should_find_gears_with_exactly_two_neighbors() {
+        let schematic = load_example();
+        let ratios: Vec<i32> = schematic.gears('*', 2).iter()
+            .map(|(_, numbers)| numbers[0].value * numbers[1].value)
+            .collect();
+        assert_eq!(ratios, vec![16345, 451490]);
+        assert_eq!(schematic.gears('*', 1).len(), 1);
+    }
+}
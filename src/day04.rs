@@ -1,8 +1,5 @@
-use std::collections::HashMap;
 use std::fs;
 
-use regex::Replacer;
-
 pub fn main() {
     let input = fs::read_to_string("./inputs/day04/input.txt").unwrap();
     part1(&input);
@@ -15,27 +12,81 @@ fn part1(input: &String) {
 }
 
 fn part2(input: &String) -> u128 {
-    let num_cards = input.lines().filter(|line| !line.is_empty()).count();
+    let wins: Vec<usize> = input.lines().filter(|line| !line.is_empty()).map(number_of_wins).collect();
+    let card_counts = copy_counts(&wins, &CopyRule::standard()).unwrap();
+
+    let total_score = card_counts.iter().sum();
+    println!("Part 2 score is {total_score}");
+    total_score
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CopyDirection { Next, Previous }
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TableEnd { Cap, Wrap }
+
+/// Describes which cards get copied when a card has `n` wins: the `n` cards after (or before) it, where cards beyond
+/// the end of the table are either dropped or wrap around to the other end. Every copy of the winning card yields
+/// `weight(i)` copies of the `i`-th card it points to (counting from 1).
+pub struct CopyRule {
+    pub direction: CopyDirection,
+    pub table_end: TableEnd,
+    pub weight: fn(usize) -> u128,
+}
+
+impl CopyRule {
+    /// The rule of the actual puzzle: one copy of each of the next cards, up to the end of the table
+    pub fn standard() -> CopyRule {
+        CopyRule { direction: CopyDirection::Next, table_end: TableEnd::Cap, weight: |_| 1 }
+    }
 
-    let mut card_counts: HashMap<usize, u128> = HashMap::new();
-    for i in 1..=num_cards  {
-        card_counts.insert(i, 1);
+    fn targets(&self, idx: usize, num_wins: usize, num_cards: usize) -> Vec<(usize, u128)> {
+        (1..=num_wins).filter_map(|i| {
+            let target = match (self.direction, self.table_end) {
+                (CopyDirection::Next, TableEnd::Cap) => Some(idx + i).filter(|t| *t < num_cards),
+                (CopyDirection::Previous, TableEnd::Cap) => idx.checked_sub(i),
+                (CopyDirection::Next, TableEnd::Wrap) => Some((idx + i) % num_cards),
+                (CopyDirection::Previous, TableEnd::Wrap) => Some((idx + num_cards - i % num_cards) % num_cards),
+            };
+            target.map(|t| (t, (self.weight)(i)))
+        }).collect()
     }
+}
 
-    for (idx, card) in input.lines().filter(|line| !line.is_empty()).enumerate() {
-        let idx = idx + 1;
-        let num_matching_numbers = number_of_wins(card);
-        let card_count = *card_counts.get(&idx).expect("Should have card");
-        for i in 1..=num_matching_numbers {
-            if idx + i <= num_cards {
-                *card_counts.get_mut(&(idx + i)).unwrap() += card_count;
+/// Number of instances of every card (original plus copies) once the cascade has settled, given the number of wins of
+/// every card. Fails when the cascade never ends, i.e. when a card (indirectly) copies itself, or when a count does not
+/// fit in a u128.
+pub fn copy_counts(wins: &[usize], rule: &CopyRule) -> Result<Vec<u128>, String> {
+    let num_cards = wins.len();
+    let targets: Vec<Vec<(usize, u128)>> = wins.iter().enumerate()
+        .map(|(idx, num_wins)| rule.targets(idx, *num_wins, num_cards))
+        .collect();
+
+    // Process cards in topological order, such that a card's count is final before it hands out its copies
+    let mut num_sources = vec![0; num_cards];
+    targets.iter().flatten().filter(|(_, weight)| *weight > 0).for_each(|(target, _)| num_sources[*target] += 1);
+    let mut ready: Vec<usize> = (0..num_cards).filter(|idx| num_sources[*idx] == 0).collect();
+    let mut card_counts = vec![1u128; num_cards];
+    let mut num_processed = 0;
+    while let Some(idx) = ready.pop() {
+        num_processed += 1;
+        for (target, weight) in targets[idx].iter().filter(|(_, weight)| *weight > 0) {
+            card_counts[*target] = card_counts[idx].checked_mul(*weight)
+                .and_then(|copies| card_counts[*target].checked_add(copies))
+                .ok_or(format!("Number of copies of card {} overflows", target + 1))?;
+            num_sources[*target] -= 1;
+            if num_sources[*target] == 0 {
+                ready.push(*target);
             }
         }
     }
 
-    let total_score = card_counts.values().sum();
-    println!("Part 2 score is {total_score}");
-    total_score
+    if num_processed < num_cards {
+        let first_in_cycle = (0..num_cards).find(|idx| num_sources[*idx] > 0).unwrap();
+        return Err(format!("Cascade never ends: card {} keeps getting copied", first_in_cycle + 1));
+    }
+    Ok(card_counts)
 }
 
 pub fn score(card: &str) -> i32 {
@@ -70,7 +121,7 @@ fn split_first(string: &str, split_at: char) -> Option<(&str, &str)> {
 mod tests {
     use std::fs;
 
-    use crate::day04::{number_of_wins, part2, score};
+    use crate::day04::{copy_counts, CopyDirection, CopyRule, number_of_wins, part2, score, TableEnd};
 
     #[test]
     fn should_count_wins_in_card_1() {
@@ -113,4 +164,36 @@ mod tests {
         let pt2 = part2(&fs::read_to_string("./inputs/day04/input_example.txt").unwrap());
         assert_eq!(pt2, 30)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_return_copy_count_per_card() {
+        let counts = copy_counts(&[4, 2, 2, 1, 0, 0], &CopyRule::standard());
+        assert_eq!(counts, Ok(vec![1, 2, 4, 8, 14, 1]));
+    }
+
+    #[test]
+    fn should_copy_previous_cards() {
+        let rule = CopyRule { direction: CopyDirection::Previous, ..CopyRule::standard() };
+        let counts = copy_counts(&[0, 0, 0, 0, 0, 4, 2, 2, 1, 0, 0], &rule);
+        assert_eq!(counts, Ok(vec![1, 7, 7, 7, 10, 6, 3, 2, 1, 1, 1]));
+    }
+
+    #[test]
+    fn should_wrap_around_the_end_of_the_table() {
+        let rule = CopyRule { table_end: TableEnd::Wrap, ..CopyRule::standard() };
+        assert_eq!(copy_counts(&[0, 0, 2], &rule), Ok(vec![2, 2, 1]));
+        assert!(copy_counts(&[1, 1, 2], &rule).is_err());
+    }
+
+    #[test]
+    fn should_apply_weighted_copies() {
+        let rule = CopyRule { weight: |i| i as u128, ..CopyRule::standard() };
+        assert_eq!(copy_counts(&[2, 1, 0], &rule), Ok(vec![1, 2, 5]));
+    }
+
+    #[test]
+    fn should_detect_overflow() {
+        let rule = CopyRule { weight: |_| u64::MAX as u128, ..CopyRule::standard() };
+        assert!(copy_counts(&[1, 1, 1, 0], &rule).is_err());
+    }
+}
@@ -11,7 +11,7 @@ pub fn main() {
 }
 
 fn part1(input: &str) -> i64 {
-    let almanac = parse(input);
+    let almanac = parse(input).unwrap();
 
     let lowest_location = almanac.seeds.iter()
         .map(|seed_range| almanac.follow(&seed_range.start)).min()
@@ -21,7 +21,7 @@ fn part1(input: &str) -> i64 {
 }
 
 fn part2(input: &str) -> i64 {
-    let almanac = parse_part2(input);
+    let almanac = parse_part2(input).unwrap();
 
     let lowest_location = almanac.seeds.par_iter()
         .enumerate()
@@ -37,7 +37,7 @@ fn part2(input: &str) -> i64 {
     lowest_location
 }
 
-pub fn parse(input: &str) -> Almanac {
+pub fn parse(input: &str) -> Result<Almanac, String> {
     let seeds_str = input.lines().find(|_| true).expect("Should have a first line");
     let seeds = get_numbers(split_first(seeds_str, ':').unwrap().1)
         .iter().map(|seed| *seed..(*seed + 1)).collect::<Vec<_>>();
@@ -45,7 +45,7 @@ pub fn parse(input: &str) -> Almanac {
     parse_maps(input, seeds)
 }
 
-pub fn parse_part2(input: &str) -> Almanac {
+pub fn parse_part2(input: &str) -> Result<Almanac, String> {
     let seeds_str = input.lines().find(|_| true).expect("Should have a first line");
     let seeds = get_numbers(split_first(seeds_str, ':').unwrap().1).chunks(2)
         .map(|chunk| {
@@ -57,26 +57,28 @@ pub fn parse_part2(input: &str) -> Almanac {
     parse_maps(input, seeds)
 }
 
-fn parse_maps(input: &str, seeds: Vec<Range<i64>>) -> Almanac {
-    input.lines().skip(2)
-        .fold(Almanac { seeds, entries: vec![] }, |mut almanac, line| {
-            let first_char = line.chars().next().unwrap_or(' ');
-            if first_char.is_alphabetic() {
-                almanac.entries.push(RangedMap {
-                    name: &*line,
-                    mapping: vec![],
-                });
-            } else if first_char.is_numeric() {
-                let [destination_start, source_start, range_length] = get_numbers(line)[..] else { panic!("Expected 3 numbers") };
-                almanac.entries.last_mut().unwrap()
-                    .mapping.push(RangedMapEntry {
-                    start: source_start,
-                    range_length: range_length,
-                    target_start: destination_start,
-                });
-            }
-            almanac
-        })
+fn parse_maps(input: &str, seeds: Vec<Range<i64>>) -> Result<Almanac, String> {
+    let mut names: Vec<&str> = vec![];
+    let mut mappings: Vec<Vec<RangedMapEntry>> = vec![];
+    for line in input.lines().skip(2) {
+        let first_char = line.chars().next().unwrap_or(' ');
+        if first_char.is_alphabetic() {
+            names.push(line);
+            mappings.push(vec![]);
+        } else if first_char.is_numeric() {
+            let [destination_start, source_start, range_length] = get_numbers(line)[..] else { panic!("Expected 3 numbers") };
+            mappings.last_mut().unwrap().push(RangedMapEntry {
+                start: source_start,
+                range_length,
+                target_start: destination_start,
+            });
+        }
+    }
+
+    let entries = names.into_iter().zip(mappings)
+        .map(|(name, mapping)| RangedMap::new(name, mapping))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Almanac { seeds, entries })
 }
 
 struct Almanac<'a> {
@@ -96,14 +98,23 @@ impl<'a> Almanac<'a> {
 
 struct RangedMap<'a> {
     name: &'a str,
+    /// Entries sorted by their source `start`, guaranteed to be non-overlapping
     mapping: Vec<RangedMapEntry>,
 }
 
 impl<'a> RangedMap<'a> {
+    /// Builds the interval index for the given entries. Fails if any two source ranges overlap, since then a
+    /// lookup would be ambiguous.
+    pub fn new(name: &'a str, mut mapping: Vec<RangedMapEntry>) -> Result<RangedMap<'a>, String> {
+        mapping.sort_by_key(|entry| entry.start);
+        if let Some(pair) = mapping.windows(2).find(|pair| pair[0].end() > pair[1].start) {
+            return Err(format!("Overlapping source ranges in '{name}': {:?} and {:?}", pair[0], pair[1]));
+        }
+        Ok(RangedMap { name, mapping })
+    }
+
     pub fn get(&self, seed: &i64) -> i64 {
-        self.mapping.iter().find(|entry|
-            &entry.start <= seed && seed < &(entry.start + entry.range_length)
-        )
+        self.find_entry(seed)
             .map(|entry| {
                 let offset_from_start = seed - entry.start;
                 entry.target_start + offset_from_start
@@ -111,18 +122,34 @@ impl<'a> RangedMap<'a> {
             .unwrap_or(*seed)
     }
 
+    /// Binary search for the entry whose source range contains `seed`, if any.
+    pub fn find_entry(&self, seed: &i64) -> Option<&RangedMapEntry> {
+        let idx = self.mapping.partition_point(|entry| entry.end() <= *seed);
+        self.mapping.get(idx).filter(|entry| entry.contains(seed))
+    }
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 struct RangedMapEntry {
     start: i64,
     range_length: i64,
     target_start: i64,
 }
 
+impl RangedMapEntry {
+    fn end(&self) -> i64 {
+        self.start + self.range_length
+    }
+
+    fn contains(&self, seed: &i64) -> bool {
+        &self.start <= seed && seed < &self.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
-    use crate::day05::{parse, part1, part2};
+    use crate::day05::{parse, part1, part2, RangedMap, RangedMapEntry};
 
     #[test]
     fn should_return_correct_minimum_seed_location() {
@@ -133,7 +160,7 @@ mod tests {
     #[test]
     fn almanac_should_correctly_map_seed_to_soil() {
         let input = &fs::read_to_string("./inputs/day05/input_example.txt").unwrap();
-        let almanac = parse(input);
+        let almanac = parse(input).unwrap();
         assert_eq!(almanac.entries[0].get(&79), 81)
     }
 
@@ -142,4 +169,32 @@ mod tests {
         let lowest_location = part2(&fs::read_to_string("./inputs/day05/input_example.txt").unwrap());
         assert_eq!(lowest_location, 46)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ranged_map_should_report_matching_entry() {
+        let input = &fs::read_to_string("./inputs/day05/input_example.txt").unwrap();
+        let almanac = parse(input).unwrap();
+        let seed_to_soil = &almanac.entries[0];
+        assert_eq!(seed_to_soil.find_entry(&79), Some(&RangedMapEntry { start: 50, range_length: 48, target_start: 52 }));
+        assert_eq!(seed_to_soil.find_entry(&99), Some(&RangedMapEntry { start: 98, range_length: 2, target_start: 50 }));
+        assert_eq!(seed_to_soil.find_entry(&49), None);
+        assert_eq!(seed_to_soil.find_entry(&100), None);
+        assert_eq!(seed_to_soil.get(&49), 49);
+    }
+
+    #[test]
+    fn ranged_map_should_reject_overlapping_source_ranges() {
+        let mapping = vec![
+            RangedMapEntry { start: 10, range_length: 5, target_start: 0 },
+            RangedMapEntry { start: 14, range_length: 3, target_start: 100 },
+        ];
+        assert!(RangedMap::new("overlapping", mapping).is_err());
+    }
+
+    #[test]
+    fn parsing_should_report_overlapping_source_ranges() {
+        let input = "seeds: 79 14\n\nseed-to-soil map:\n50 98 2\n52 50 49\n";
+        let error = parse(input).err().expect("Overlapping ranges should be rejected");
+        assert!(error.contains("seed-to-soil map:"), "Unexpected error: {error}");
+    }
+}
@@ -1,6 +1,11 @@
+use std::fmt::Debug;
 use std::fs;
 use std::iter::zip;
 use std::ops::Range;
+use std::str::FromStr;
+
+use num::Integer;
+use num::integer::Roots;
 
 use crate::common::{get_numbers, split_first};
 
@@ -36,13 +41,13 @@ fn parse_part1(input: &str) -> Vec<(i64, i64)> {
     zip(times, distances).map(|(t, d)| (*t, *d)).collect()
 }
 
-fn parse_part2(input: &str) -> (i64, i64) {
+fn parse_part2<T: FromStr + Clone>(input: &str) -> (T, T) where T::Err: Debug {
     let x = input.lines().take(2).map(|line| {
         let (_, num_str) = split_first(line, ':').expect("Contains :");
-        num_str.replace(' ', "").parse::<i64>().unwrap()
+        num_str.replace(' ', "").parse::<T>().unwrap()
     }).collect::<Vec<_>>();
     let [time, distance] = x.as_slice() else { panic!("Expected exactly 2 input lines") };
-    (*time, *distance)
+    (time.clone(), distance.clone())
 }
 
 // not used, but just figuring out how to create higher order functions
@@ -52,11 +57,36 @@ fn distance_fn(allowed_time: &i64) -> impl Fn(i64) -> i64 {
 }
 
 fn times_that_beat_record(allowed_time: &i64, record: &i64) -> Range<i64> {
-    // We want to solve (allowed_time - t) * t > record. Equality is given at:
-    let min_t =  ((*allowed_time as f64) - ((allowed_time.pow(2) - 4*record) as f64).sqrt()) / 2f64;
-    let max_t =  ((*allowed_time as f64) + ((allowed_time.pow(2) - 4*record) as f64).sqrt()) / 2f64;
+    winning_hold_times(allowed_time, record, &1)
+}
+
+/// Exact range of button hold times `h` for which the boat beats the record, when the boat gains `acceleration`
+/// speed per millisecond of holding. That is, we want to solve `acceleration * h * (allowed_time - h) > record`.
+/// Only uses integer arithmetic, so it also works for `i128` or `BigInt` sized races.
+fn winning_hold_times<T: Integer + Roots + Clone>(allowed_time: &T, record: &T, acceleration: &T) -> Range<T> {
+    let beats_record = |hold: &T| acceleration.clone() * hold.clone() * (allowed_time.clone() - hold.clone()) > *record;
+    let two = T::one() + T::one();
+
+    // The distance is maximal halfway, so if that does not beat the record nothing will
+    let halfway = allowed_time.clone() / two.clone();
+    if !beats_record(&halfway) {
+        return T::zero()..T::zero();
+    }
+
+    // Equality is given at h = (a*T - sqrt(a^2 T^2 - 4 a R)) / 2a, which is correct up to rounding of the square root
+    let a_t = acceleration.clone() * allowed_time.clone();
+    let discriminant = a_t.clone() * a_t.clone() - two.clone() * two.clone() * acceleration.clone() * record.clone();
+    let mut min_hold = (a_t - discriminant.sqrt()) / (two * acceleration.clone());
+    while min_hold < halfway && !beats_record(&min_hold) {
+        min_hold = min_hold + T::one();
+    }
+    while min_hold > T::zero() && beats_record(&(min_hold.clone() - T::one())) {
+        min_hold = min_hold - T::one();
+    }
 
-    (min_t.floor() as i64 + 1)..(max_t.ceil() as i64)
+    // Holding for h or for allowed_time - h gives the same distance
+    let max_hold = allowed_time.clone() - min_hold.clone();
+    min_hold..(max_hold + T::one())
 }
 
 
@@ -64,7 +94,9 @@ fn times_that_beat_record(allowed_time: &i64, record: &i64) -> Range<i64> {
 mod tests {
     use std::fs;
 
-    use crate::day06::{part1, part2};
+    use num::BigInt;
+
+    use crate::day06::{parse_part2, part1, part2, times_that_beat_record, winning_hold_times};
 
     #[test]
     fn should_return_correct_total_number_of_winning_times() {
@@ -77,4 +109,41 @@ mod tests {
         let x = part2(&fs::read_to_string("./inputs/day06/input_example.txt").unwrap());
         assert_eq!(x, 71503)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_return_exact_winning_intervals_of_example_races() {
+        assert_eq!(times_that_beat_record(&7, &9), 2..6);
+        assert_eq!(times_that_beat_record(&15, &40), 4..12);
+        assert_eq!(times_that_beat_record(&30, &200), 11..20);
+    }
+
+    #[test]
+    fn when_record_cannot_be_beaten_should_return_empty_interval() {
+        assert!(times_that_beat_record(&7, &12).is_empty());
+        assert!(times_that_beat_record(&0, &0).is_empty());
+    }
+
+    #[test]
+    fn should_support_non_unit_acceleration() {
+        // 3 * h * (7 - h) is 18 for h = 1, 30 for h = 2 and 36 for h = 3
+        assert_eq!(winning_hold_times(&7, &29, &3), 2..6);
+        assert_eq!(winning_hold_times(&7, &30, &3), 3..5);
+        assert!(winning_hold_times(&7, &36, &3).is_empty());
+    }
+
+    #[test]
+    fn should_solve_huge_races_exactly() {
+        let (time, record) = parse_part2::<i128>(&fs::read_to_string("./inputs/day06/input_example.txt").unwrap());
+        let interval = winning_hold_times(&time, &record, &1);
+        assert_eq!(interval.end - interval.start, 71503);
+
+        let time: BigInt = "100000000000000000000000000000".parse().unwrap();
+        let record: BigInt = "2000000000000000000000000000000000000000000000000000000000".parse().unwrap();
+        let interval = winning_hold_times(&time, &record, &BigInt::from(1));
+        let beats = |h: &BigInt| h * (&time - h) > record;
+        assert!(beats(&interval.start));
+        assert!(!beats(&(&interval.start - 1)));
+        assert!(beats(&(&interval.end - 1)));
+        assert!(!beats(&interval.end));
+    }
+}
@@ -1,11 +1,10 @@
 use std::cmp::Ordering;
-use std::cmp::Ordering::{Equal, Greater, Less};
 use std::collections::HashMap;
 use std::fs;
-use std::iter::zip;
+
+use itertools::Itertools;
 
 use crate::common::split_first;
-use crate::day07::HandType::{FiveOfAKind, FourOfAKind, FullHouse, HighCard, OnePair, ThreeOfAKind, TwoPair};
 
 pub fn main() {
     let input = &fs::read_to_string("./inputs/day07/input.txt").unwrap();
@@ -14,204 +13,147 @@ pub fn main() {
 }
 
 fn part1(input: &str) -> i64 {
-    let mut hands = input.lines().map(|line| Hand::new(line)).collect::<Vec<_>>();
-    hands.sort();
-    let winnings = hands.iter().enumerate().map(|(rank, hand)| {
-        let rank = rank as i64 + 1;
-        println!("Rank {rank}: {:?}", hand);
-        rank * hand.bid
-    }).sum();
+    let winnings = total_winnings(input, &Ruleset::part1());
     println!("Total winnings for part 1: {winnings}");
     winnings
 }
 
 fn part2(input: &str) -> i64 {
-    let mut hands = input.lines().map(|line| Hand::new_pt2(line)).collect::<Vec<_>>();
-    hands.sort_by(|a,b | {
-        cmp_hands_pt2(a, b)
-    });
-    let winnings = hands.iter().enumerate().map(|(rank, hand)| {
+    let winnings = total_winnings(input, &Ruleset::part2());
+    println!("Total winnings for part 2: {winnings}");
+    winnings
+}
+
+fn total_winnings(input: &str, ruleset: &Ruleset) -> i64 {
+    let mut hands = input.lines().map(|line| Hand::new(line, ruleset)).collect::<Vec<_>>();
+    hands.sort();
+    hands.iter().enumerate().map(|(rank, hand)| {
         let rank = rank as i64 + 1;
         println!("Rank {rank}: {:?}", hand);
         rank * hand.bid
-    }).sum();
-    println!("Total winnings for part 1: {winnings}");
-    winnings
+    }).sum()
 }
 
-#[derive(Eq, PartialEq, Debug)]
-pub struct Hand {
-    cards: [char; 5],
-    bid: i64,
-    hand_type: HandType,
+/// Everything that determines how hands are parsed and ranked.
+pub struct Ruleset {
+    /// All valid cards, from weakest to strongest
+    card_order: Vec<char>,
+    /// Cards that act as whatever card gives the strongest hand type
+    wildcards: Vec<char>,
+    hand_size: usize,
+    /// Hand types from strongest to weakest; a hand gets the first type that matches. Types should only depend on
+    /// which cards are in the hand, not on their order.
+    hand_types: Vec<HandTypeRule>,
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone)]
-enum HandType {
-    FiveOfAKind,
-    FourOfAKind,
-    FullHouse,
-    ThreeOfAKind,
-    TwoPair,
-    OnePair,
-    HighCard,
+pub struct HandTypeRule {
+    name: &'static str,
+    matches: fn(&[char]) -> bool,
 }
 
-
-impl Hand {
-    pub fn new(input: &str) -> Self {
-        let (cards_string, bid_string) = split_first(input, ' ').unwrap();
-        let cards = cards_string.chars().take(5).collect::<Vec<_>>().try_into().unwrap();
-        Self {
-            cards: cards,
-            bid: bid_string.parse().expect("Bid should be a valid number"),
-            hand_type: determine_type(&cards),
-        }
-    }
-
-    pub fn new_pt2(input: &str) -> Self {
-        let (cards_string, bid_string) = split_first(input, ' ').unwrap();
-        let cards = cards_string.chars().take(5).collect::<Vec<_>>().try_into().unwrap();
-        Self {
-            cards: cards,
-            bid: bid_string.parse().expect("Bid should be a valid number"),
-            hand_type: determine_type_pt2(&cards),
-        }
-    }
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone)]
+pub struct HandType {
+    /// Position in the ruleset's ranking, where 0 is the strongest type
+    rank: usize,
+    name: &'static str,
 }
 
-impl PartialOrd<Self> for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl Ruleset {
+    pub fn part1() -> Ruleset {
+        Ruleset {
+            card_order: "23456789TJQKA".chars().collect(),
+            wildcards: vec![],
+            hand_size: 5,
+            hand_types: standard_hand_types(),
+        }
     }
-}
 
-impl Ord for Hand {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let type_cmp = self.hand_type.cmp(&other.hand_type).reverse();
-        match type_cmp {
-            Equal => cmp_char_arrays(&self.cards, &other.cards, cmp_chars),
-            _ => type_cmp,
+    pub fn part2() -> Ruleset {
+        Ruleset {
+            card_order: "J23456789TQKA".chars().collect(),
+            wildcards: vec!['J'],
+            ..Ruleset::part1()
         }
     }
-}
-
-fn cmp_chars(a: &char, b: &char) -> Ordering {
-    if a == b { return Equal; }
 
-    if a.is_numeric() {
-        return if b.is_numeric() { a.cmp(b) /* comparing chars, not numbers */ } else { Less };
+    fn card_value(&self, card: char) -> usize {
+        self.card_order.iter().position(|c| *c == card)
+            .unwrap_or_else(|| panic!("Card '{card}' is not part of this ruleset"))
     }
 
-    if b.is_numeric() /* && !a.is_numeric() */ {
-        return Greater;
+    /// The strongest hand type that can be made with the given cards, using any card for the wildcards
+    pub fn determine_type(&self, cards: &[char]) -> HandType {
+        let (wild, fixed): (Vec<char>, Vec<char>) = cards.iter().partition(|c| self.wildcards.contains(c));
+        let replacements: Vec<char> = self.card_order.iter().filter(|c| !self.wildcards.contains(c)).copied().collect();
+
+        replacements.iter().combinations_with_replacement(wild.len())
+            .map(|replacement| {
+                let candidate = fixed.iter().chain(replacement).copied().collect::<Vec<_>>();
+                self.hand_types.iter().position(|hand_type| (hand_type.matches)(&candidate))
+                    .expect("Every hand should match some hand type")
+            })
+            .min()
+            .map(|rank| HandType { rank, name: self.hand_types[rank].name })
+            .expect("Should have at least one non-wildcard card")
     }
-
-    // Botha a and b alphabetic
-    let letter_order = ['A', 'K', 'Q', 'J', 'T'];
-    let idx_a = letter_order.iter().position(|c| *c == *a).unwrap();
-    let idx_b = letter_order.iter().position(|c| *c == *b).unwrap();
-    return idx_a.cmp(&idx_b).reverse();
-}
-
-fn cmp_char_arrays(a: &[char; 5], b: &[char; 5], char_comparator: fn(&char, &char) -> Ordering) -> Ordering {
-    zip(a, b).filter_map(|(ai, bi)| {
-        if ai != bi {
-            Some(char_comparator(ai, bi))
-        } else {
-            None
-        }
-    }).next().unwrap_or(Equal)
 }
 
-fn determine_type(cards: &[char; 5]) -> HandType {
+fn group_sizes(cards: &[char]) -> Vec<usize> {
     let frequencies = cards.iter().fold(HashMap::new(), |mut map, c| {
         *map.entry(c).or_insert(0) += 1;
         map
     });
-    if frequencies.values().any(|f| *f == 5) {
-        FiveOfAKind
-    } else if frequencies.values().any(|f| *f == 4) {
-        FourOfAKind
-    } else if frequencies.values().any(|f| *f == 3) && frequencies.values().any(|f| *f == 2) {
-        FullHouse
-    } else if frequencies.values().any(|f| *f == 3) {
-        ThreeOfAKind
-    } else if frequencies.values().filter(|&f| *f == 2).count() == 2 {
-        TwoPair
-    } else if frequencies.values().any(|f| *f == 2) {
-        OnePair
-    } else {
-        HighCard
-    }
+    frequencies.into_values().sorted().rev().collect()
 }
 
-fn determine_type_pt2(cards: &[char; 5]) -> HandType {
-    let frequencies = cards.iter().fold(HashMap::new(), |mut map, c| {
-        *map.entry(c).or_insert(0) += 1;
-        map
-    });
+fn standard_hand_types() -> Vec<HandTypeRule> {
+    vec![
+        HandTypeRule { name: "Five of a kind", matches: |cards| group_sizes(cards)[0] >= 5 },
+        HandTypeRule { name: "Four of a kind", matches: |cards| group_sizes(cards)[0] == 4 },
+        HandTypeRule { name: "Full house", matches: |cards| group_sizes(cards)[0] >= 3 && group_sizes(cards).get(1).is_some_and(|size| *size >= 2) },
+        HandTypeRule { name: "Three of a kind", matches: |cards| group_sizes(cards)[0] == 3 },
+        HandTypeRule { name: "Two pair", matches: |cards| matches!(group_sizes(cards)[..], [2, 2, ..]) },
+        HandTypeRule { name: "One pair", matches: |cards| group_sizes(cards)[0] == 2 },
+        HandTypeRule { name: "High card", matches: |_| true },
+    ]
+}
 
-    if frequencies.get(&'J').is_none() {
-        println!("\nHand {:?} doesn't contain any Jokers", cards);
-        return determine_type(cards);
-    }
+#[derive(Eq, PartialEq, Debug)]
+pub struct Hand {
+    cards: Vec<char>,
+    bid: i64,
+    hand_type: HandType,
+    /// Value of each card according to the ruleset, used to break ties between hands of the same type
+    card_values: Vec<usize>,
+}
 
-    println!("\nHand {:?} contains Jokers, determining best use of them...", cards);
-    let mut cards_to_try = frequencies.keys().filter(|c| ***c != 'J').collect::<Vec<_>>();
-    if cards_to_try.is_empty() {
-        cards_to_try = vec![&&'A']
+impl Hand {
+    pub fn new(input: &str, ruleset: &Ruleset) -> Self {
+        let (cards_string, bid_string) = split_first(input, ' ').unwrap();
+        let cards = cards_string.chars().collect::<Vec<_>>();
+        if cards.len() != ruleset.hand_size {
+            panic!("Expected {} cards but got '{cards_string}'", ruleset.hand_size);
+        }
+        Self {
+            hand_type: ruleset.determine_type(&cards),
+            card_values: cards.iter().map(|c| ruleset.card_value(*c)).collect(),
+            cards,
+            bid: bid_string.parse().expect("Bid should be a valid number"),
+        }
     }
-
-    let perms = cards.iter().enumerate()
-        .fold(vec![vec![]], |acc, (idx, c)| {
-            let nxt_gen = acc.iter().flat_map(|mut sub_str: &Vec<char>| {
-                let extra_stuff: Vec<Vec<char>> = if *c != 'J' {
-                    let mut clone = sub_str.clone();
-                    clone.push(*c);
-
-                    vec![clone]
-                } else {
-                    println!("Seeing how we can replace {c} at idx {idx}");
-                    cards_to_try.iter().map(|replacement| {
-                        println!(" - trying to replace joker with {replacement}");
-                        let mut clone = sub_str.clone();
-                        clone.push(***replacement);
-                        clone
-                    }).collect()
-                };
-                extra_stuff
-            }).collect::<Vec<Vec<char>>>();
-            nxt_gen
-        });
-    println!("All possible permutations for {:?}:\n  > {:?}",cards, perms);
-    let mut possibilities = perms.iter().map(|perm| {
-        let arr: &[char] = perm.as_slice();
-        if arr.len() != 5 { panic!() }
-        let arr: &[char; 5] = <&[char; 5]>::try_from(arr).unwrap();
-        (arr, determine_type(arr))
-    }).collect::<Vec<_>>();
-    possibilities.sort_by(|a, b| a.1.cmp(&b.1));
-    let (a, b) = possibilities.first().unwrap();
-    println!("Best use of jokers is {:?} which gives type {:?}", a, b);
-    *b
 }
 
-fn cmp_hands_pt2(a: &Hand, b: &Hand) -> Ordering {
-    let type_cmp = a.hand_type.cmp(&b.hand_type).reverse();
-    match type_cmp {
-        Equal => cmp_char_arrays(&a.cards, &b.cards, cmp_chars_pt2),
-        _ => type_cmp,
+impl PartialOrd<Self> for Hand {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-fn cmp_chars_pt2(a: &char, b: &char) -> Ordering {
-    if a == b { return Equal; }
-
-    let letter_order = ['A', 'K', 'Q', 'T','9', '8', '7', '6', '5', '4', '3', '2', 'J'];
-    let idx_a = letter_order.iter().position(|c| *c == *a).unwrap();
-    let idx_b = letter_order.iter().position(|c| *c == *b).unwrap();
-    return idx_a.cmp(&idx_b).reverse();
+impl Ord for Hand {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.hand_type.cmp(&other.hand_type).reverse()
+            .then_with(|| self.card_values.cmp(&other.card_values))
+    }
 }
 
 #[cfg(test)]
@@ -219,23 +161,23 @@ mod tests {
     use std::cmp::Ordering::{Greater, Less};
     use std::fs;
 
-    use crate::day07::{Hand, part1, part2};
-    use crate::day07::HandType::TwoPair;
+    use crate::day07::{Hand, HandType, HandTypeRule, part1, part2, Ruleset};
 
     #[test]
     fn should_correctly_parse_string_into_hand() {
-        let hand = Hand::new("KTJJT 220");
+        let hand = Hand::new("KTJJT 220", &Ruleset::part1());
         assert_eq!(hand, Hand {
-            cards: ['K', 'T', 'J', 'J', 'T'],
+            cards: vec!['K', 'T', 'J', 'J', 'T'],
             bid: 220,
-            hand_type: TwoPair,
+            hand_type: HandType { rank: 4, name: "Two pair" },
+            card_values: vec![11, 8, 9, 9, 8],
         });
     }
 
     #[test]
     fn should_correctly_compare_hands() {
         let hands = fs::read_to_string("./inputs/day07/input_example.txt").unwrap().lines().map(|line|
-            Hand::new(line)
+            Hand::new(line, &Ruleset::part1())
         ).collect::<Vec<_>>();
         let [h1, h4, h3, h2, h5] = hands.try_into().unwrap();
         // QQQJA is largest three of a kind
@@ -262,8 +204,8 @@ mod tests {
 
     #[test]
     fn bugfix__should_correctly_compare_numbers() {
-        let hand1 = Hand::new("A9AAA 220");
-        let hand2 = Hand::new("A8AAA 220");
+        let hand1 = Hand::new("A9AAA 220", &Ruleset::part1());
+        let hand2 = Hand::new("A8AAA 220", &Ruleset::part1());
 
         assert_eq!(hand1.cmp(&hand2), Greater)
     }
@@ -279,4 +221,35 @@ mod tests {
         let x = part2(&fs::read_to_string("./inputs/day07/input_example.txt").unwrap());
         assert_eq!(x, 5905)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn jokers_should_act_as_the_best_card() {
+        let ruleset = Ruleset::part2();
+        assert_eq!(ruleset.determine_type(&['K', 'T', 'J', 'J', 'T']).name, "Four of a kind");
+        assert_eq!(ruleset.determine_type(&['J', 'J', 'J', 'J', 'J']).name, "Five of a kind");
+        assert_eq!(Hand::new("JKKK2 1", &ruleset).cmp(&Hand::new("QQQQ2 1", &ruleset)), Less);
+    }
+
+    #[test]
+    fn should_support_multiple_wildcards() {
+        let ruleset = Ruleset { wildcards: vec!['J', '2'], ..Ruleset::part2() };
+        assert_eq!(ruleset.determine_type(&['A', '2', 'J', '3', '4']).name, "Three of a kind");
+    }
+
+    #[test]
+    fn should_support_larger_hands() {
+        let ruleset = Ruleset { hand_size: 6, ..Ruleset::part1() };
+        assert_eq!(Hand::new("AAAKKK 1", &ruleset).hand_type.name, "Full house");
+        assert_eq!(Hand::new("AAAAKK 1", &ruleset).hand_type.name, "Four of a kind");
+    }
+
+    #[test]
+    fn should_support_custom_hand_types() {
+        let mut hand_types = vec![HandTypeRule { name: "Royal", matches: |cards| cards.iter().all(|c| "TJQKA".contains(*c)) }];
+        hand_types.extend(Ruleset::part2().hand_types);
+        let ruleset = Ruleset { hand_types, ..Ruleset::part2() };
+        assert_eq!(ruleset.determine_type(&['K', 'Q', 'J', 'T', 'A']).name, "Royal");
+        assert_eq!(ruleset.determine_type(&['K', 'Q', '2', 'T', 'A']).name, "High card");
+        assert_eq!(Hand::new("TJQKA 1", &ruleset).cmp(&Hand::new("22222 1", &ruleset)), Greater);
+    }
+}
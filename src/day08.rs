@@ -1,6 +1,7 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use crate::common::{lcm, split_first};
+use itertools::Itertools;
+use crate::common::{chinese_remainder, split_first};
 
 pub fn main() {
     let input = &fs::read_to_string("./inputs/day08/input.txt").unwrap();
@@ -16,13 +17,9 @@ fn part1(input: &str) -> i64 {
 }
 
 fn part2(input: &str) -> i64 {
-    let is_at_end = |node: &str| node.ends_with('Z');
     let (instructions, map) = parse(input);
-    let individual_steps = map.keys()
-        .filter(|&&node_name| node_name.ends_with('A'))
-        .map(|start_node| steps_required(instructions, &map, start_node, is_at_end))
-        .collect::<Vec<_>>();
-    let steps = individual_steps.iter().copied().reduce(|a, b| { lcm(a, b) }).unwrap();
+    let starts = map.keys().copied().filter(|node_name| node_name.ends_with('A')).collect::<Vec<_>>();
+    let steps = simultaneous_steps_required(instructions, &map, &starts, |node| node.ends_with('Z')).unwrap();
     println!("For part 2 it took {steps} steps.");
     steps
 }
@@ -35,11 +32,7 @@ fn steps_required(
 ) -> i64 {
     let (mut current, mut steps) = (start, 0);
     for instruction in instructions.chars().cycle() {
-        let node = map.get(current).unwrap();
-        current = match instruction {
-            'L' => node.0,
-            _ => node.1,
-        };
+        current = step(map, current, instruction);
         steps += 1;
         if is_at_end(current) {
             break
@@ -48,6 +41,192 @@ fn steps_required(
     steps
 }
 
+fn step<'a>(map: &BTreeMap<&'a str, (&'a str, &'a str)>, current: &str, instruction: char) -> &'a str {
+    let node = map.get(current).unwrap();
+    match instruction {
+        'L' => node.0,
+        _ => node.1,
+    }
+}
+
+/// The steps (including step 0) at which a single ghost is at an end node. Since the walk is deterministic in
+/// (node, instruction index), it eventually loops: after `cycle_start` steps it repeats every `cycle_length` steps.
+#[derive(Debug, Eq, PartialEq)]
+struct GhostCycle {
+    /// Steps before the cycle starts at which the ghost is at an end node
+    prefix_hits: Vec<i64>,
+    cycle_start: i64,
+    cycle_length: i64,
+    /// Steps in `cycle_start..cycle_start + cycle_length` at which the ghost is at an end node
+    cycle_hits: Vec<i64>,
+}
+
+impl GhostCycle {
+    fn is_at_end(&self, steps: i64) -> bool {
+        if steps < self.cycle_start {
+            self.prefix_hits.contains(&steps)
+        } else {
+            let offset = (steps - self.cycle_start) % self.cycle_length;
+            self.cycle_hits.contains(&(self.cycle_start + offset))
+        }
+    }
+}
+
+fn ghost_cycle(
+    instructions: &str,
+    map: &BTreeMap<&str, (&str, &str)>,
+    start: &str,
+    is_at_end: fn(&str) -> bool
+) -> GhostCycle {
+    let instructions = instructions.chars().collect::<Vec<_>>();
+    let mut first_seen: HashMap<(&str, usize), i64> = HashMap::new();
+    let mut hits = vec![];
+    let (mut current, mut steps) = (start, 0);
+    loop {
+        let instruction_idx = steps as usize % instructions.len();
+        if let Some(cycle_start) = first_seen.insert((current, instruction_idx), steps) {
+            let (prefix_hits, cycle_hits) = hits.into_iter().partition(|hit| *hit < cycle_start);
+            return GhostCycle { prefix_hits, cycle_start, cycle_length: steps - cycle_start, cycle_hits };
+        }
+        if is_at_end(current) {
+            hits.push(steps);
+        }
+        current = step(map, current, instructions[instruction_idx]);
+        steps += 1;
+    }
+}
+
+/// Smallest number of steps after which all ghosts are at an end node at the same time. Combines the cycles of the
+/// individual ghosts exactly, so it does not rely on every ghost reaching an end node at multiples of its cycle length.
+fn simultaneous_steps_required(
+    instructions: &str,
+    map: &BTreeMap<&str, (&str, &str)>,
+    starts: &[&str],
+    is_at_end: fn(&str) -> bool
+) -> Result<i64, String> {
+    let cycles = starts.iter().map(|start| ghost_cycle(instructions, map, start, is_at_end)).collect::<Vec<_>>();
+
+    // Before every ghost is in its cycle we simply try every step
+    let all_cycling_from = cycles.iter().map(|cycle| cycle.cycle_start).max().unwrap_or(0);
+    if let Some(steps) = (1..all_cycling_from).find(|steps| cycles.iter().all(|cycle| cycle.is_at_end(*steps))) {
+        return Ok(steps);
+    }
+
+    // Afterwards, try every combination of hits within the cycles
+    cycles.iter()
+        .map(|cycle| cycle.cycle_hits.iter().map(|hit| (*hit as i128, cycle.cycle_length as i128)).collect::<Vec<_>>())
+        .multi_cartesian_product()
+        .filter_map(|congruences| chinese_remainder(&congruences))
+        .map(|(steps, period)| {
+            let from = all_cycling_from.max(1) as i128;
+            steps + ((from - steps).max(0) + period - 1) / period * period
+        })
+        .min()
+        .map(|steps| steps as i64)
+        .ok_or(format!("The ghosts starting at {:?} never arrive at an end node simultaneously", starts))
+}
+
+/// The nodes visited (in order) by a ghost once it is in its cycle, see [GhostCycle]
+fn cycle_path<'a>(instructions: &str, map: &BTreeMap<&'a str, (&'a str, &'a str)>, start: &'a str) -> Vec<&'a str> {
+    let cycle = ghost_cycle(instructions, map, start, |_| false);
+    let path = instructions.chars().cycle()
+        .scan(start, |current, instruction| {
+            let visited = *current;
+            *current = step(map, current, instruction);
+            Some(visited)
+        });
+    path.skip(cycle.cycle_start as usize).take(cycle.cycle_length as usize).collect()
+}
+
+/// Graphviz representation of the network, with edges labeled by the instruction that follows them
+fn to_dot(map: &BTreeMap<&str, (&str, &str)>) -> String {
+    let edges = map.iter()
+        .flat_map(|(node, (left, right))| [
+            format!("    \"{node}\" -> \"{left}\" [label=\"L\"];"),
+            format!("    \"{node}\" -> \"{right}\" [label=\"R\"];"),
+        ])
+        .join("\n");
+    format!("digraph network {{\n{edges}\n}}\n")
+}
+
+fn neighbors<'a>(map: &BTreeMap<&'a str, (&'a str, &'a str)>, node: &str) -> [&'a str; 2] {
+    let (left, right) = map.get(node).unwrap();
+    [*left, *right]
+}
+
+/// Strongly connected components of the network (Tarjan's algorithm), each sorted by node name
+fn strongly_connected_components<'a>(map: &BTreeMap<&'a str, (&'a str, &'a str)>) -> Vec<Vec<&'a str>> {
+    struct Tarjan<'a, 'm> {
+        map: &'m BTreeMap<&'a str, (&'a str, &'a str)>,
+        index: HashMap<&'a str, usize>,
+        low_link: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: HashSet<&'a str>,
+        components: Vec<Vec<&'a str>>,
+    }
+
+    impl<'a> Tarjan<'a, '_> {
+        fn visit(&mut self, node: &'a str) {
+            let idx = self.index.len();
+            self.index.insert(node, idx);
+            self.low_link.insert(node, idx);
+            self.stack.push(node);
+            self.on_stack.insert(node);
+
+            for next in neighbors(self.map, node) {
+                if !self.index.contains_key(next) {
+                    self.visit(next);
+                    let low = self.low_link[node].min(self.low_link[next]);
+                    self.low_link.insert(node, low);
+                } else if self.on_stack.contains(next) {
+                    let low = self.low_link[node].min(self.index[next]);
+                    self.low_link.insert(node, low);
+                }
+            }
+
+            if self.low_link[node] == self.index[node] {
+                let mut component = vec![];
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack.remove(member);
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort();
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan { map, index: HashMap::new(), low_link: HashMap::new(), stack: vec![], on_stack: HashSet::new(), components: vec![] };
+    for node in map.keys() {
+        if !tarjan.index.contains_key(node) {
+            tarjan.visit(node);
+        }
+    }
+    tarjan.components
+}
+
+/// All nodes that can be reached from any of the starts, ignoring starts that are not part of the network
+fn reachable_from<'a>(map: &BTreeMap<&'a str, (&'a str, &'a str)>, starts: &[&'a str]) -> BTreeSet<&'a str> {
+    let mut to_visit = starts.iter().copied().filter(|start| map.contains_key(start)).collect::<Vec<_>>();
+    let mut reachable: BTreeSet<&str> = to_visit.iter().copied().collect();
+    while let Some(node) = to_visit.pop() {
+        for next in neighbors(map, node) {
+            if reachable.insert(next) {
+                to_visit.push(next);
+            }
+        }
+    }
+    reachable
+}
+
+fn unreachable_nodes<'a>(map: &BTreeMap<&'a str, (&'a str, &'a str)>, starts: &[&'a str]) -> Vec<&'a str> {
+    let reachable = reachable_from(map, starts);
+    map.keys().copied().filter(|node| !reachable.contains(node)).collect()
+}
+
 fn parse(input: &str) -> (&str, BTreeMap<&str, (&str, &str)>) {
     let instructions = input.lines().next().unwrap();
     let map: BTreeMap<&str, (&str, &str)> = input.lines().skip(2).map(|line| {
@@ -61,7 +240,7 @@ fn parse(input: &str) -> (&str, BTreeMap<&str, (&str, &str)>) {
 
 #[cfg(test)]
 mod tests {
-    use crate::day08::{part1, part2};
+    use crate::day08::{cycle_path, ghost_cycle, GhostCycle, parse, part1, part2, simultaneous_steps_required, strongly_connected_components, to_dot, unreachable_nodes};
 
     #[test]
     fn example_1_should_be_computed_correctly(){
@@ -105,4 +284,96 @@ XXX = (XXX, XXX)";
         let steps = part2(input);
         assert_eq!(steps, 6)
     }
-}
\ No newline at end of file
+
+    const GHOSTS_WITH_PREFIX: &str = r"L
+
+11A = (11B, XXX)
+11B = (11C, XXX)
+11C = (11Z, XXX)
+11Z = (11Y, XXX)
+11Y = (11Z, XXX)
+22A = (22Z, XXX)
+22Z = (22B, XXX)
+22B = (22C, XXX)
+22C = (22Z, XXX)
+XXX = (XXX, XXX)";
+
+    #[test]
+    fn should_detect_prefix_and_cycle_of_a_ghost() {
+        let (instructions, map) = parse(GHOSTS_WITH_PREFIX);
+        let cycle = ghost_cycle(instructions, &map, "11A", |node| node.ends_with('Z'));
+        assert_eq!(cycle, GhostCycle { prefix_hits: vec![], cycle_start: 3, cycle_length: 2, cycle_hits: vec![3] });
+        let cycle = ghost_cycle(instructions, &map, "22A", |node| node.ends_with('Z'));
+        assert_eq!(cycle, GhostCycle { prefix_hits: vec![], cycle_start: 1, cycle_length: 3, cycle_hits: vec![1] });
+    }
+
+    #[test]
+    fn should_combine_cycles_that_are_not_aligned_with_first_hit() {
+        // Hits at 3, 5, 7, ... and at 1, 4, 7, ..., while the lcm of the first hits would give 3
+        let (instructions, map) = parse(GHOSTS_WITH_PREFIX);
+        let steps = simultaneous_steps_required(instructions, &map, &["11A", "22A"], |node| node.ends_with('Z'));
+        assert_eq!(steps, Ok(7));
+    }
+
+    #[test]
+    fn should_report_when_ghosts_never_arrive_simultaneously() {
+        let input = r"L
+
+11A = (11Z, XXX)
+11Z = (11B, XXX)
+11B = (11Z, XXX)
+22A = (22B, XXX)
+22B = (22Z, XXX)
+22Z = (22C, XXX)
+22C = (22Z, XXX)
+XXX = (XXX, XXX)";
+        let (instructions, map) = parse(input);
+        let steps = simultaneous_steps_required(instructions, &map, &["11A", "22A"], |node| node.ends_with('Z'));
+        assert!(steps.is_err());
+    }
+
+    #[test]
+    fn should_export_network_as_dot() {
+        let (_, map) = parse("LLR\n\nAAA = (BBB, BBB)\nBBB = (AAA, ZZZ)\nZZZ = (ZZZ, ZZZ)");
+        assert_eq!(to_dot(&map), r#"digraph network {
+    "AAA" -> "BBB" [label="L"];
+    "AAA" -> "BBB" [label="R"];
+    "BBB" -> "AAA" [label="L"];
+    "BBB" -> "ZZZ" [label="R"];
+    "ZZZ" -> "ZZZ" [label="L"];
+    "ZZZ" -> "ZZZ" [label="R"];
+}
+"#);
+    }
+
+    #[test]
+    fn should_find_strongly_connected_components() {
+        let (_, map) = parse("LLR\n\nAAA = (BBB, BBB)\nBBB = (AAA, ZZZ)\nZZZ = (ZZZ, ZZZ)");
+        let mut components = strongly_connected_components(&map);
+        components.sort();
+        assert_eq!(components, vec![vec!["AAA", "BBB"], vec!["ZZZ"]]);
+    }
+
+    #[test]
+    fn should_find_unreachable_nodes() {
+        let (_, map) = parse(GHOSTS_WITH_PREFIX);
+        assert_eq!(unreachable_nodes(&map, &["AAA", "11A"]), vec!["22A", "22B", "22C", "22Z"]);
+        assert_eq!(unreachable_nodes(&map, &["11A", "22A"]), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn should_report_the_cycle_a_start_falls_into() {
+        let (instructions, map) = parse(GHOSTS_WITH_PREFIX);
+        assert_eq!(cycle_path(instructions, &map, "11A"), vec!["11Z", "11Y"]);
+        assert_eq!(cycle_path(instructions, &map, "22A"), vec!["22Z", "22B", "22C"]);
+    }
+
+    #[test]
+    fn should_count_start_that_is_part_of_its_own_cycle() {
+        let (instructions, map) = parse("L\n\nAAA = (AAA, AAA)");
+        let cycle = ghost_cycle(instructions, &map, "AAA", |_| true);
+        assert_eq!(cycle, GhostCycle { prefix_hits: vec![], cycle_start: 0, cycle_length: 1, cycle_hits: vec![0] });
+        let steps = simultaneous_steps_required(instructions, &map, &["AAA"], |_| true);
+        assert_eq!(steps, Ok(1));
+    }
+}
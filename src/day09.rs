@@ -1,4 +1,5 @@
 use std::fs;
+use num::{BigInt, ToPrimitive, Zero};
 use crate::common::get_numbers;
 
 pub fn main() {
@@ -8,56 +9,95 @@ pub fn main() {
 }
 
 fn part1(input: &str) -> i64 {
-    let sum = parse(input).into_iter().map(find_next).sum();
+    let sum: BigInt = parse(input).iter().map(|sequence| extrapolate(sequence, 1).unwrap()).sum();
     println!("Part 1: Sum of next sequence elements is: {sum}");
-    sum
+    sum.to_i64().unwrap()
 }
 
 fn part2(input: &str) -> i64 {
-    let sum = parse(input).into_iter()
-        .map(|mut sequence| { sequence.reverse(); sequence} )
-        .map(find_next)
-        .sum();
+    let sum: BigInt = parse(input).iter().map(|sequence| extrapolate(sequence, -1).unwrap()).sum();
     println!("Part 2: Sum of previous sequence elements is: {sum}");
-    sum
+    sum.to_i64().unwrap()
 }
 
-fn parse(input: &str) -> Vec<Vec<i64>> {
-    input.lines().map(|line| get_numbers::<i64>(line)).collect()
+fn parse(input: &str) -> Vec<Vec<BigInt>> {
+    input.lines().map(|line| get_numbers::<BigInt>(line)).collect()
 }
 
-fn find_next(sequence: Vec<i64>) -> i64 {
-    let differences: Vec<i64> = sequence.windows(2).map(|window| {
-        let [a, b] = window else { panic!("Expected window size equal to 2") };
-        b - a
-    }).collect();
+/// The next element of a sequence that fits in an `i64`, see [extrapolate]
+fn find_next(sequence: Vec<i64>) -> Result<i64, String> {
+    let sequence = sequence.into_iter().map(BigInt::from).collect::<Vec<_>>();
+    let next = extrapolate(&sequence, 1)?;
+    next.to_i64().ok_or(format!("Next element {next} does not fit in an i64"))
+}
+
+/// Extrapolates the polynomial sequence `steps` elements beyond its last element, or before its first element when
+/// `steps` is negative. Fails if the differences do not settle to all zeroes within the length of the sequence, as
+/// then the sequence is not (provably) polynomial.
+fn extrapolate(sequence: &[BigInt], steps: i64) -> Result<BigInt, String> {
+    if steps < 0 {
+        let reversed = sequence.iter().rev().cloned().collect::<Vec<_>>();
+        return extrapolate(&reversed, -steps);
+    }
+
+    // Newton's formula: a_{n-1+k} = sum_j C(k+j-1, j) * d_j, with d_j the last element of the j-th differences
+    let last_differences = last_differences(sequence)?;
+    let mut binomial = BigInt::from(1);
+    let mut result = BigInt::zero();
+    for (j, difference) in last_differences.iter().enumerate() {
+        if j > 0 {
+            binomial = binomial * (steps + j as i64 - 1) / j as i64;
+        }
+        result += &binomial * difference;
+    }
+    Ok(result)
+}
 
-    *sequence.last().unwrap() + if differences.iter().all(|&x| x == 0) {
-        0
-    } else {
-        find_next(differences)
+/// Last element of the sequence, of its differences, of the differences of those, etc., up to the last non-zero row
+fn last_differences(sequence: &[BigInt]) -> Result<Vec<BigInt>, String> {
+    let mut last_differences = vec![];
+    let mut differences = sequence.to_vec();
+    while !differences.iter().all(|x| x.is_zero()) {
+        if differences.len() == 1 {
+            return Err(format!("Sequence {:?} is not polynomial: its differences never settle to zero", sequence));
+        }
+        last_differences.push(differences.last().unwrap().clone());
+        differences = differences.windows(2).map(|window| {
+            let [a, b] = window else { panic!("Expected window size equal to 2") };
+            b - a
+        }).collect();
+    }
+    if differences.is_empty() {
+        return Err("Cannot extrapolate an empty sequence".to_string());
     }
+    Ok(last_differences)
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::day09::{find_next, part1, part2};
+    use num::BigInt;
+
+    use crate::day09::{extrapolate, find_next, part1, part2};
+
+    fn big(sequence: &[i64]) -> Vec<BigInt> {
+        sequence.iter().map(|x| BigInt::from(*x)).collect()
+    }
 
     #[test]
     fn should_find_next_element_in_constant_sequence() {
-        assert_eq!(find_next(vec![3, 3, 3, 3, 3]), 3);
+        assert_eq!(find_next(vec![3, 3, 3, 3, 3]), Ok(3));
     }
 
     #[test]
     fn should_find_next_element_in_linear_sequence() {
-        assert_eq!(find_next(vec![2,4,6,8,10]), 12);
+        assert_eq!(find_next(vec![2,4,6,8,10]), Ok(12));
     }
 
     #[test]
     fn should_find_next_element_in_quadratic_sequence() {
-        assert_eq!(find_next(vec![1,4,9,16]), 25);
-        assert_eq!(find_next(vec![3,6,11,18]), 27);  // offset by 2
+        assert_eq!(find_next(vec![1,4,9,16]), Ok(25));
+        assert_eq!(find_next(vec![3,6,11,18]), Ok(27));  // offset by 2
     }
 
     #[test]
@@ -77,4 +117,30 @@ mod tests {
         let sum = part2(input);
         assert_eq!(sum, 2)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_extrapolate_multiple_steps_forward_and_backward() {
+        let squares = big(&[1, 4, 9, 16]);
+        assert_eq!(extrapolate(&squares, 3), Ok(BigInt::from(49)));
+        assert_eq!(extrapolate(&squares, 0), Ok(BigInt::from(16)));
+        assert_eq!(extrapolate(&squares, -1), Ok(BigInt::from(0)));
+        assert_eq!(extrapolate(&squares, -4), Ok(BigInt::from(9)));
+    }
+
+    #[test]
+    fn should_extrapolate_beyond_i64() {
+        let cubes = big(&[1_000_000, 8_000_000, 27_000_000, 64_000_000, 125_000_000]);
+        let expected: BigInt = "1000000000000000000000000000".parse().unwrap();
+        assert_eq!(extrapolate(&cubes, 1_000_000_000 - 5), Ok(expected * 1_000_000));
+    }
+
+    #[test]
+    fn when_sequence_is_not_polynomial_should_return_error() {
+        assert!(extrapolate(&big(&[1, 2, 4, 8, 16, 32]), 1).is_err());
+        assert!(extrapolate(&big(&[5]), 1).is_err());
+        assert!(extrapolate(&big(&[]), 1).is_err());
+        assert_eq!(extrapolate(&big(&[0]), 1), Ok(BigInt::from(0)));
+        assert!(find_next(vec![5]).is_err());
+        assert!(find_next(vec![0, i64::MAX]).is_err());
+    }
+}
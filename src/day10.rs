@@ -5,7 +5,6 @@ use std::slice::Iter;
 use std::str::FromStr;
 
 use crate::day10::Direction::{E, N, S, W};
-use crate::day10::NextStep::{Continue, DeadEnd, Start};
 use crate::day10::Tile::{EW, GROUND, NE, NS, NW, SE, START, SW};
 
 pub fn main() {
@@ -16,15 +15,47 @@ pub fn main() {
 
 fn part1(input: &str) -> i64 {
     let map = parse(input);
-    let (_, loop_coords) = find_loop_coords(&map);
+    let (_, loop_coords) = find_loop_coords(&map).unwrap();
     println!("Part 1: number of steps farthest from start is {}", loop_coords.len() / 2);
     (loop_coords.len() / 2) as i64
 }
 
 fn part2(input: &str) -> i64 {
-    let (sx, sy) = input.lines().enumerate().filter_map(|(y, line)| line.find('S').map(|x| (x, y))).next().unwrap();
-    let mut map = parse(input);
-    let (start_tile, loop_coords) = find_loop_coords(&map);
+    let map = parse(input);
+    let (start_tile, loop_coords) = find_loop_coords(&map).unwrap();
+    let inside_count = enclosed_tiles(&map, start_tile, &loop_coords).len();
+    println!("Part 2: number of enclosed tiles is {inside_count}");
+    inside_count as i64
+}
+
+/// Tiles enclosed by the loop, found by scanning each row and keeping track of how often we crossed the loop.
+/// We only count loop tiles that connect to the north: a vertical pipe is a crossing, and of the corner pairs `F-J` and
+/// `L-7` (a crossing) exactly one connects north, while of `F-7` and `L-J` (touching the loop, not crossing) either
+/// none or both do.
+fn enclosed_tiles(map: &Map, start_tile: Tile, loop_coords: &[Coord<usize>]) -> HashSet<Coord<usize>> {
+    let on_loop: HashSet<&Coord<usize>> = loop_coords.iter().collect();
+    let mut enclosed = HashSet::new();
+    for y in 0..map_height(map) {
+        let mut inside = false;
+        for x in 0..map_width(map) {
+            if on_loop.contains(&(x, y)) {
+                let tile = if get_tile(map, (x, y)) == START { start_tile } else { get_tile(map, (x, y)) };
+                if matches!(tile, NS | NE | NW) {
+                    inside = !inside;
+                }
+            } else if inside {
+                enclosed.insert((x, y));
+            }
+        }
+    }
+    enclosed
+}
+
+/// Tiles enclosed by the loop, found by flood filling the outside of a 3x enlarged map. Uses a lot more memory than
+/// [enclosed_tiles], but doesn't need to reason about crossing the loop.
+fn enclosed_tiles_upscaled(map: &Map, start_tile: Tile, loop_coords: &[Coord<usize>]) -> HashSet<Coord<usize>> {
+    let mut map = map.clone();
+    let (sx, sy) = loop_coords[0];
     map[sy][sx] = start_tile;
 
     let loop_coords: HashSet<&Coord<usize>> = loop_coords.iter().collect();
@@ -36,15 +67,14 @@ fn part2(input: &str) -> i64 {
     let outside = flood_fill((0, 0), 3 * map_width(&map), 3 * map_height(&map), |(x, y)| !enhanced[*y][*x]);
     outside.iter().for_each(|(x, y)| enhanced[*y][*x] = true);
 
-    let mut inside_count = 0;
+    let mut enclosed = HashSet::new();
     for x in 0..map_width(&map) {
         for y in 0..map_height(&map) {
             let is_filled = (0..3).any(|dy| (0..3).any(|dx| enhanced[3*y + dy][3*x + dx]));
-            if !is_filled { inside_count += 1; }
+            if !is_filled { enclosed.insert((x, y)); }
         }
     }
-    println!("Part 2: number of enclosed tiles is {inside_count}");
-    inside_count as i64
+    enclosed
 }
 
 // Replaces each tile with a 3x3 version. If tile is part of the loop we enlarge the pipe, else we
@@ -114,83 +144,67 @@ fn parse(input: &str) -> Map {
     input.lines().map(|line| line.chars().map(Tile::from_char).collect()).collect()
 }
 
-fn find_loop_coords(map: &Map) -> (Tile, Vec<(usize, usize)>) {
-    let (start_x, start_y) = map.iter().enumerate().find_map(|(y, row)| {
-        row.iter().enumerate()
-            .find_map(|(x, c)| if c == &START { Some(x) } else { None })
-            .map(|x| (x, y))
-    }).expect("Should contain a starting pipe 'S'!");
-
-    let loop_coords = Tile::pipes_iter().filter_map(|start_tile| {
-        // println!("\n\nTrying with start tile {start_tile}:");
-        let result = start_tile.to_string().chars()
-            .filter_map(|c| {
-                // todo: start tile must be connected on both sides, so we can just find any
-                //       matching pipes, and then for each pipe just try a single direction
-
-                let mut heading = Direction::from_str(c.to_string().as_str()).unwrap();
-                let mut coord = (start_x, start_y);
-                let mut tile = *start_tile;
-
-                let mut loop_coords: Vec<(usize, usize)> = vec![];
-                loop {
-                    loop_coords.push(coord);
-                    let outcome = next_coord(map, coord, heading, *start_tile);
-                    match outcome {
-                        DeadEnd => { return None; }
-                        Start => { return Some((*start_tile, loop_coords)); }
-                        Continue((t, c, h)) => { (tile, coord, heading) = (t, c, h); }
-                    };
-                }
-                panic!("Unreachable")
-            })
-            .next();
-        result
-    }).next();
-
-    loop_coords.unwrap()
+/// Finds the loop through the starting tile 'S', returning the pipe hidden under 'S' and the coordinates of the loop in
+/// walking order, beginning at the start.
+fn find_loop_coords(map: &Map) -> Result<(Tile, Vec<Coord<usize>>), String> {
+    let starts = map.iter().enumerate()
+        .flat_map(|(y, row)| row.iter().enumerate().filter(|(_, tile)| **tile == START).map(move |(x, _)| (x, y)))
+        .collect::<Vec<_>>();
+    let start = match starts[..] {
+        [start] => start,
+        [] => return Err("Map does not contain a starting tile 'S'".to_string()),
+        _ => return Err(format!("Map contains {} starting tiles 'S' at {:?}", starts.len(), starts)),
+    };
+
+    let start_tile = infer_start_tile(map, start)?;
+    let mut heading = Direction::from_str(&start_tile.to_string()[..1]).unwrap();
+    let mut coord = start;
+    let mut loop_coords = vec![];
+    loop {
+        loop_coords.push(coord);
+        coord = step_from(map, coord, heading)
+            .ok_or(format!("Loop is not closed: pipe at {:?} leads {heading} off the map", coord))?;
+        if coord == start {
+            return Ok((start_tile, loop_coords));
+        }
+        heading = next_heading(get_tile(map, coord), heading)
+            .ok_or(format!("Loop is not closed: {} tile at {:?} can't be entered heading {heading}", get_tile(map, coord), coord))?;
+    }
 }
 
-
-fn next_coord(map: &Map, current: (usize, usize), current_heading: Direction, start_tile_replacement: Tile) -> NextStep {
-    let (current_x, current_y) = current;
-    if (current_heading == W && current_x == 0) || (current_heading == N && current_y == 0)
-        || (current_heading == E && current_x > map_width(map))
-        || (current_heading == S && current_y >= map_height(map)) {
-        return DeadEnd;
+/// Determines the pipe under 'S' from the neighboring pipes that connect to it
+fn infer_start_tile(map: &Map, start: Coord<usize>) -> Result<Tile, String> {
+    let connections = Direction::iter()
+        .filter(|direction| step_from(map, start, **direction)
+            .is_some_and(|neighbor| next_heading(get_tile(map, neighbor), **direction).is_some()))
+        .map(|direction| direction.to_string())
+        .collect::<Vec<_>>();
+    if connections.len() != 2 {
+        return Err(format!("Starting tile at {:?} has {} connecting pipes {:?}, expected 2", start, connections.len(), connections));
     }
+    Ok(*Tile::pipes_iter()
+        .find(|tile| connections.iter().all(|direction| tile.to_string().contains(direction)))
+        .unwrap())
+}
 
-    let next_coord = move_from(current_x, current_y, current_heading);
-    let next_tile = get_tile(map, next_coord);
-    if next_tile == GROUND {
-        DeadEnd
-    } else {
-        let next_heading = if next_tile == START {
-            next_heading(start_tile_replacement, current_heading)
-        } else {
-            next_heading(next_tile, current_heading)
-        };
-        if next_heading.is_none() {
-            DeadEnd
-        } else {
-            let next_heading = next_heading.unwrap();
-            let mut current_tile = get_tile(map, current);
-            if current_tile == START {
-                current_tile = start_tile_replacement;
-            }
+/// The loop's corners in walking order, which together describe the loop as a polygon
+fn loop_vertices(map: &Map, start_tile: Tile, loop_coords: &[Coord<usize>]) -> Vec<Coord<usize>> {
+    loop_coords.iter()
+        .filter(|coord| {
+            let tile = if get_tile(map, **coord) == START { start_tile } else { get_tile(map, **coord) };
+            tile != NS && tile != EW
+        })
+        .copied()
+        .collect()
+}
 
-            if (next_tile == START && current_tile.connects_to(&start_tile_replacement, current_heading))
-                || current_tile.connects_to(&next_tile, current_heading) {
-                if next_tile == START {
-                    Start
-                } else {
-                    Continue((next_tile, next_coord, next_heading))
-                }
-            } else {
-                println!("  Can't go from {current_tile} tile in {current_heading} to a {next_tile} tile.");
-                DeadEnd
-            }
-        }
+fn step_from(map: &Map, (x, y): Coord<usize>, heading: Direction) -> Option<Coord<usize>> {
+    if (heading == W && x == 0) || (heading == N && y == 0)
+        || (heading == E && x + 1 >= map_width(map))
+        || (heading == S && y + 1 >= map_height(map)) {
+        None
+    } else {
+        Some(move_from(x, y, heading))
     }
 }
 
@@ -203,13 +217,6 @@ fn move_from(x: usize, y: usize, heading: Direction) -> (usize, usize) {
     }
 }
 
-enum NextStep {
-    DeadEnd,
-    Start,
-    Continue((Tile, (usize, usize), Direction)),
-}
-
-
 fn next_heading(tile: Tile, current_heading: Direction) -> Option<Direction> {
     if tile == START {
         return None;
@@ -309,10 +316,45 @@ impl fmt::Display for Tile {
 mod tests {
     use std::fs;
 
-    use crate::day10::{find_loop_coords, next_heading, parse, part1, part2};
+    use crate::day10::{enclosed_tiles, enclosed_tiles_upscaled, find_loop_coords, loop_vertices, next_heading, parse, part1, part2};
     use crate::day10::Direction::{E, N, S, W};
     use crate::day10::Tile::{EW, NE, NS, NW, SE, SW};
 
+    const EXAMPLE_PART2_1: &str = r"..........
+.S------7.
+.|F----7|.
+.||....||.
+.||....||.
+.|L-7F-J|.
+.|..||..|.
+.L--JL--J.
+..........";
+    const EXAMPLE_PART2_2: &str = r".F----7F7F7F7F-7....
+.|F--7||||||||FJ....
+.||.FJ||||||||L7....
+FJL7L7LJLJ||LJ.L-7..
+L--J.L7...LJS7F-7L7.
+....F-J..F7FJ|L7L7L7
+....L7.F7||L7|.L7L7|
+.....|FJLJ|FJ|F7|.LJ
+....FJL-7.||.||||...
+....L---J.LJ.LJLJ...";
+    const EXAMPLE_PART2_3: &str = r"FF7FSF7F7F7F7F7F---7
+L|LJ||||||||||||F--J
+FL-7LJLJ||||||LJL-77
+F--JF--7||LJLJ7F7FJ-
+L---JF-JLJ.||-FJLJJ7
+|F|F-JF---7F7-L7L|7|
+|FFJF7L7F-JF7|JL---7
+7-L-JL7||F7|L7F-7F7|
+L.L7LFJ|||||FJL7||LJ
+L7JLJL-JLJLJL--JLJ.L";
+    const EXAMPLE_2: &str = r"..F7.
+.FJ|.
+SJ.L7
+|F--J
+LJ...";
+
     #[test]
     fn should_correctly_determine_next_heading() {
         assert_eq!(next_heading(NS, S), Some(S));
@@ -343,7 +385,7 @@ mod tests {
 .|.|.
 .L-J.
 .....");
-        let loop_length = find_loop_coords(&map).1.len();
+        let loop_length = find_loop_coords(&map).unwrap().1.len();
         assert_eq!(loop_length, 8);
     }
 
@@ -354,7 +396,7 @@ mod tests {
 SJ.L7
 |F--J
 LJ...");
-        let loop_length = find_loop_coords(&map).1.len();
+        let loop_length = find_loop_coords(&map).unwrap().1.len();
         assert_eq!(loop_length, 16);
     }
 
@@ -365,7 +407,7 @@ LJ...");
 SJLL7
 |F--J
 LJ.LJ");
-        let loop_length = find_loop_coords(&map).1.len();
+        let loop_length = find_loop_coords(&map).unwrap().1.len();
         assert_eq!(loop_length, 16);
     }
 
@@ -418,5 +460,56 @@ L.L7LFJ|||||FJL7||LJ
 L7JLJL-JLJLJL--JLJ.L");
         assert_eq!(x, 10);
     }
-}
 
+    #[test]
+    fn should_infer_start_tile_and_order_loop_from_start() {
+        let map = parse(EXAMPLE_2);
+        let (start_tile, loop_coords) = find_loop_coords(&map).unwrap();
+        assert_eq!(start_tile, SE);
+        assert_eq!(loop_coords[..3], [(0, 2), (0, 3), (0, 4)]);
+        assert_eq!(loop_coords.last(), Some(&(1, 2)));
+    }
+
+    #[test]
+    fn loop_vertices_should_describe_the_enclosed_polygon() {
+        let map = parse(EXAMPLE_2);
+        let (start_tile, loop_coords) = find_loop_coords(&map).unwrap();
+        let vertices = loop_vertices(&map, start_tile, &loop_coords);
+        assert_eq!(vertices.len(), 12);
+
+        // Shoelace formula and Pick's theorem give the number of enclosed tiles
+        let double_area: i64 = vertices.iter().zip(vertices.iter().cycle().skip(1))
+            .map(|((x1, y1), (x2, y2))| (*x1 as i64) * (*y2 as i64) - (*x2 as i64) * (*y1 as i64))
+            .sum();
+        let enclosed = double_area.abs() / 2 - loop_coords.len() as i64 / 2 + 1;
+        assert_eq!(enclosed, part2(EXAMPLE_2));
+    }
+
+    #[test]
+    fn should_report_malformed_maps() {
+        assert!(find_loop_coords(&parse("F-7\n|.|\nL-J")).unwrap_err().contains("does not contain"));
+        assert!(find_loop_coords(&parse("S-7\n|.|\nL-S")).unwrap_err().contains("2 starting tiles"));
+        assert!(find_loop_coords(&parse("...\n.S.\n...")).unwrap_err().contains("0 connecting pipes"));
+        assert!(find_loop_coords(&parse(".|.\n-S-\n.|.")).unwrap_err().contains("4 connecting pipes"));
+        assert!(find_loop_coords(&parse("S-7\n|.|\nL-|")).unwrap_err().contains("not closed"));
+    }
+
+    #[test]
+    fn scanline_should_agree_with_upscaling_on_all_examples() {
+        for input in [EXAMPLE_2, EXAMPLE_PART2_1, EXAMPLE_PART2_2, EXAMPLE_PART2_3] {
+            let map = parse(input);
+            let (start_tile, loop_coords) = find_loop_coords(&map).unwrap();
+            let scanline = enclosed_tiles(&map, start_tile, &loop_coords);
+            assert_eq!(scanline, enclosed_tiles_upscaled(&map, start_tile, &loop_coords), "Mismatch for\n{input}");
+        }
+    }
+
+    #[test]
+    fn should_return_enclosed_coordinates() {
+        let map = parse(EXAMPLE_PART2_1);
+        let (start_tile, loop_coords) = find_loop_coords(&map).unwrap();
+        let mut enclosed = enclosed_tiles(&map, start_tile, &loop_coords).into_iter().collect::<Vec<_>>();
+        enclosed.sort();
+        assert_eq!(enclosed, vec![(2, 6), (3, 6), (6, 6), (7, 6)]);
+    }
+}
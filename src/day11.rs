@@ -1,5 +1,4 @@
 use std::collections::HashSet;
-use std::convert::identity;
 use std::fs;
 
 pub fn main() {
@@ -38,37 +37,143 @@ fn galaxy_coords(image: &Vec<Vec<bool>>) -> Vec<(usize, usize)> {
     ).collect()
 }
 
+/// Sum of the Manhattan distances between all pairs of galaxies. Per axis, the i-th smallest coordinate is the larger
+/// one in exactly i pairs, so after sorting we don't need to look at the pairs individually.
 fn sum_of_distances(galaxy_coordinates: &Vec<(usize, usize)>) -> usize {
-    galaxy_coordinates.iter().flat_map(|(ax, ay)|
-        galaxy_coordinates.iter().map(|(bx, by)|
-            ax.abs_diff(*bx) + ay.abs_diff(*by)
-        )
-    ).sum::<usize>() / 2  // we count every pair twice: a->b and b->a
+    let axis_sum = |mut values: Vec<usize>| {
+        values.sort();
+        values.iter().enumerate().fold((0, 0), |(sum, prefix_sum), (i, value)| {
+            (sum + i * value - prefix_sum, prefix_sum + value)
+        }).0
+    };
+    axis_sum(galaxy_coordinates.iter().map(|(x, _)| *x).collect())
+        + axis_sum(galaxy_coordinates.iter().map(|(_, y)| *y).collect())
 }
 
+fn distance((ax, ay): &(usize, usize), (bx, by): &(usize, usize)) -> usize {
+    ax.abs_diff(*bx) + ay.abs_diff(*by)
+}
+
+/// For every galaxy the index of the closest other galaxy and the distance to it. Of equally close galaxies the one
+/// with the lowest index is returned.
+fn nearest_galaxies(galaxy_coordinates: &[(usize, usize)]) -> Vec<Option<(usize, usize)>> {
+    let mut by_x = (0..galaxy_coordinates.len()).collect::<Vec<_>>();
+    by_x.sort_by_key(|idx| galaxy_coordinates[*idx]);
+    // The galaxies of each column, sorted by y
+    let columns = by_x.chunk_by(|a, b| galaxy_coordinates[*a].0 == galaxy_coordinates[*b].0).collect::<Vec<_>>();
+
+    let mut nearest = vec![None; galaxy_coordinates.len()];
+    for (position, column) in columns.iter().enumerate() {
+        for idx in column.iter() {
+            let best = closer_galaxy(galaxy_coordinates, *idx, columns[..=position].iter().rev(), None);
+            nearest[*idx] = closer_galaxy(galaxy_coordinates, *idx, columns[position + 1..].iter(), best);
+        }
+    }
+    nearest
+}
+
+/// Walks over the columns, sorted by increasing x-distance to the galaxy, until the x-distance alone is too large to
+/// beat the closest galaxy found so far (or tie with it, which could still win on index)
+fn closer_galaxy<'a>(
+    galaxy_coordinates: &[(usize, usize)],
+    idx: usize,
+    columns: impl Iterator<Item=&'a &'a [usize]>,
+    mut best: Option<(usize, usize)>,
+) -> Option<(usize, usize)> {
+    let galaxy = &galaxy_coordinates[idx];
+    for column in columns {
+        if best.is_some_and(|(_, dist)| galaxy.0.abs_diff(galaxy_coordinates[column[0]].0) > dist) {
+            break;
+        }
+        let (above, below) = column.split_at(column.partition_point(|other| galaxy_coordinates[*other].1 < galaxy.1));
+        best = closer_galaxy_in_column(galaxy_coordinates, idx, above.iter().rev(), best);
+        best = closer_galaxy_in_column(galaxy_coordinates, idx, below.iter(), best);
+    }
+    best
+}
+
+/// Walks over galaxies of a single column, sorted by increasing y-distance to the galaxy, so their distance to it only
+/// grows and we can stop at the first one farther away than the closest galaxy found so far
+fn closer_galaxy_in_column<'a>(
+    galaxy_coordinates: &[(usize, usize)],
+    idx: usize,
+    candidates: impl Iterator<Item=&'a usize>,
+    mut best: Option<(usize, usize)>,
+) -> Option<(usize, usize)> {
+    for other in candidates.filter(|other| **other != idx) {
+        let dist = distance(&galaxy_coordinates[idx], &galaxy_coordinates[*other]);
+        if best.is_some_and(|(_, best_dist)| dist > best_dist) {
+            break;
+        }
+        if best.is_none_or(|(best_idx, best_dist)| (dist, *other) < (best_dist, best_idx)) {
+            best = Some((*other, dist));
+        }
+    }
+    best
+}
+
+/// Indices of the two galaxies farthest apart, together with their distance. Uses that |dx| + |dy| is the maximum of
+/// |(ax + ay) - (bx + by)| and |(ax - ay) - (bx - by)|.
+fn farthest_pair(galaxy_coordinates: &[(usize, usize)]) -> Option<(usize, usize, usize)> {
+    [1, -1].iter().filter_map(|sign| {
+        let diagonal = |(x, y): &&(usize, usize)| *x as i64 + sign * *y as i64;
+        let (min_idx, _) = galaxy_coordinates.iter().enumerate().min_by_key(|(_, galaxy)| diagonal(galaxy))?;
+        let (max_idx, _) = galaxy_coordinates.iter().enumerate().max_by_key(|(_, galaxy)| diagonal(galaxy))?;
+        Some((min_idx, max_idx, distance(&galaxy_coordinates[min_idx], &galaxy_coordinates[max_idx])))
+    }).max_by_key(|(_, _, dist)| *dist)
+}
+
+/// How much empty rows or columns grow: all by the same factor, or each by its own factor (indexed by row or column)
+enum Expansion {
+    Factor(usize),
+    PerLine(Vec<usize>),
+}
+
+impl Expansion {
+    fn factor(&self, line: usize) -> usize {
+        match self {
+            Expansion::Factor(factor) => *factor,
+            Expansion::PerLine(factors) => factors[line],
+        }
+    }
+}
 
 fn expand_coords(coordinates: &Vec<(usize, usize)>, expansion_factor: usize) -> Vec<(usize, usize)> {
-    let (max_x, _) = *coordinates.iter().max_by_key(|(x, y)| x).unwrap();
-    let (_, max_y) = *coordinates.iter().max_by_key(|(x, y)| y).unwrap();
+    expand_coords_per_axis(coordinates, &Expansion::Factor(expansion_factor), &Expansion::Factor(expansion_factor))
+}
 
-    let mut empty_columns = (0..max_x).filter(|&x|
-        !coordinates.iter().any(|&(gx, _)| gx == x)
-    ).collect::<HashSet<_>>();
-    let mut empty_rows = (0..max_y).filter(|&y|
-        !coordinates.iter().any(|&(_, gy)| gy == y)
-    ).collect::<HashSet<_>>();
+fn expand_coords_per_axis(coordinates: &[(usize, usize)], columns: &Expansion, rows: &Expansion) -> Vec<(usize, usize)> {
+    let new_x = expanded_positions(coordinates.iter().map(|(x, _)| *x), columns);
+    let new_y = expanded_positions(coordinates.iter().map(|(_, y)| *y), rows);
+    coordinates.iter().map(|(x, y)| (new_x[*x], new_y[*y])).collect()
+}
 
-    coordinates.iter().map(|(x, y)| {
-        let empty_rows_infront = empty_rows.iter().filter(|&row_y| row_y < &y).count();
-        let empty_columns_infront = empty_columns.iter().filter(|&col_x| col_x < &x).count();
-        (empty_columns_infront * (expansion_factor - 1) + *&x, empty_rows_infront * (expansion_factor - 1) + y)
-    }).collect::<Vec<_>>()
+/// Position after expansion of every line up to the largest one containing a galaxy
+fn expanded_positions(occupied: impl Iterator<Item=usize>, expansion: &Expansion) -> Vec<usize> {
+    let occupied = occupied.collect::<HashSet<_>>();
+    let max = occupied.iter().copied().max().unwrap_or(0);
+    (0..=max).scan(0, |position, line| {
+        let current = *position;
+        *position += if occupied.contains(&line) { 1 } else { expansion.factor(line) };
+        Some(current)
+    }).collect()
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::day11::{part1, part2};
+    use crate::day11::{distance, Expansion, expand_coords, expand_coords_per_axis, farthest_pair, galaxy_coords, nearest_galaxies, parse, part1, part2, sum_of_distances};
+
+    const EXAMPLE: &str = r"...#......
+.......#..
+#.........
+..........
+......#...
+.#........
+.........#
+..........
+.......#..
+#...#.....";
 
     #[test]
     fn example_part1() {
@@ -129,4 +234,71 @@ mod tests {
 #...#.....";
         assert_eq!(part2(input, 100), 8410)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_expand_rows_and_columns_separately() {
+        let galaxies = galaxy_coords(&parse(EXAMPLE));
+        let expanded = expand_coords_per_axis(&galaxies, &Expansion::Factor(1), &Expansion::Factor(10));
+        assert_eq!(expanded[0], (3, 0));
+        assert_eq!(expanded[7], (0, 27));
+        assert_eq!(expand_coords_per_axis(&galaxies, &Expansion::Factor(3), &Expansion::Factor(3)), expand_coords(&galaxies, 3));
+    }
+
+    #[test]
+    fn should_expand_each_row_by_its_own_weight() {
+        let galaxies = galaxy_coords(&parse(EXAMPLE));
+        // Only rows 3 and 7 are empty
+        let row_weights = Expansion::PerLine(vec![0, 0, 0, 5, 0, 0, 0, 100, 0, 0]);
+        let expanded = expand_coords_per_axis(&galaxies, &Expansion::Factor(1), &row_weights);
+        assert_eq!(expanded.iter().map(|(_, y)| *y).collect::<Vec<_>>(), vec![0, 1, 2, 8, 9, 10, 111, 112, 112]);
+    }
+
+    #[test]
+    fn fast_sum_should_match_all_pairs() {
+        let galaxies = expand_coords(&galaxy_coords(&parse(EXAMPLE)), 7);
+        let all_pairs: usize = galaxies.iter().enumerate()
+            .flat_map(|(i, a)| galaxies[i + 1..].iter().map(move |b| distance(a, b)))
+            .sum();
+        assert_eq!(sum_of_distances(&galaxies), all_pairs);
+    }
+
+    #[test]
+    fn should_find_nearest_galaxy_of_each_galaxy() {
+        let example = expand_coords(&galaxy_coords(&parse(EXAMPLE)), 2);
+        // Galaxy 1 is equally far from galaxies 0 and 2
+        let ties = vec![(7, 0), (5, 0), (3, 0), (5, 2)];
+        let column = vec![(4, 9), (4, 1), (2, 5), (4, 4), (6, 6), (4, 0), (4, 7), (5, 3)];
+        for galaxies in [example, ties, column] {
+            let nearest = nearest_galaxies(&galaxies);
+            for (idx, galaxy) in galaxies.iter().enumerate() {
+                let expected = galaxies.iter().enumerate()
+                    .filter(|(other, _)| *other != idx)
+                    .map(|(other, other_galaxy)| (distance(galaxy, other_galaxy), other))
+                    .min()
+                    .map(|(dist, other)| (other, dist));
+                assert_eq!(nearest[idx], expected, "Wrong nearest galaxy for galaxy {idx} of {galaxies:?}");
+            }
+        }
+        assert_eq!(nearest_galaxies(&[(3, 3)]), vec![None]);
+    }
+
+    #[test]
+    fn should_find_nearest_galaxies_in_a_single_long_column() {
+        let galaxies = (0..50_000).map(|i| (7, 3 * i)).collect::<Vec<_>>();
+        let nearest = nearest_galaxies(&galaxies);
+        assert_eq!(nearest[0], Some((1, 3)));
+        for (idx, nearest) in nearest.iter().enumerate().skip(1) {
+            assert_eq!(*nearest, Some((idx - 1, 3)));
+        }
+    }
+
+    #[test]
+    fn should_find_farthest_pair() {
+        let galaxies = expand_coords(&galaxy_coords(&parse(EXAMPLE)), 2);
+        let max_distance = galaxies.iter().flat_map(|a| galaxies.iter().map(|b| distance(a, b))).max().unwrap();
+        let (a, b, dist) = farthest_pair(&galaxies).unwrap();
+        assert_eq!(dist, max_distance);
+        assert_eq!(distance(&galaxies[a], &galaxies[b]), max_distance);
+        assert_eq!(farthest_pair(&[]), None);
+    }
+}
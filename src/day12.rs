@@ -1,3 +1,4 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fs;
 use std::iter::repeat;
@@ -10,40 +11,53 @@ pub fn main() {
     part2(input);
 }
 
-fn part1(input: &str) -> usize {
-    let sum_configs = input.lines().map(|line|
-        num_valid_configs(line)
-    ).sum();
+fn part1(input: &str) -> u128 {
+    let sum_configs = configs_per_line(input, 1, "?").iter().sum();
     println!("Part 1: sum of all possible configurations is {sum_configs}");
     sum_configs
 }
 
-fn part2(input: &str) -> usize {
-    let sum_configs = input.lines().map(|line|
-        num_valid_configs(unfold(line).as_str())
-    ).sum();
+fn part2(input: &str) -> u128 {
+    let sum_configs = configs_per_line(input, 5, "?").iter().sum();
     println!("Part 2: sum of all possible configurations is {sum_configs}");
     sum_configs
 }
 
+/// Number of valid configurations of every line after unfolding it `copies` times
+fn configs_per_line(input: &str, copies: usize, separator: &str) -> Vec<u128> {
+    input.lines().map(|line| num_valid_configs(&unfold_with(line, copies, separator))).collect()
+}
+
+/// Indices and counts of the `n` lines with the most valid configurations after unfolding, largest first
+fn dominant_lines(input: &str, copies: usize, separator: &str, n: usize) -> Vec<(usize, u128)> {
+    configs_per_line(input, copies, separator).into_iter().enumerate()
+        .sorted_by_key(|(idx, count)| (Reverse(*count), *idx))
+        .take(n)
+        .collect()
+}
+
 fn unfold(folded_line: &str) -> String {
+    unfold_with(folded_line, 5, "?")
+}
+
+fn unfold_with(folded_line: &str, copies: usize, separator: &str) -> String {
     let (config_str, groups_str) = split_first(folded_line, ' ').unwrap();
-    let unfolded_config = repeat(config_str).take(5).intersperse("?").collect::<String>();
-    let unfolded_groups = repeat(groups_str).take(5).intersperse(",").collect::<String>();
+    let unfolded_config = repeat(config_str).take(copies).intersperse(separator).collect::<String>();
+    let unfolded_groups = repeat(groups_str).take(copies).intersperse(",").collect::<String>();
     unfolded_config + " " + &*unfolded_groups
 }
 
-fn num_valid_configs(springs_line: &str) -> usize {
+fn num_valid_configs(springs_line: &str) -> u128 {
     let (config_str, groups_str) = split_first(springs_line, ' ').unwrap();
     let groups = get_numbers::<usize>(groups_str.replace(",", " ").as_str());
     valid_configs(config_str, &groups)
 }
 
-fn valid_configs(spring_config: &str, groups: &Vec<usize>) -> usize {
+pub fn valid_configs(spring_config: &str, groups: &Vec<usize>) -> u128 {
     valid_configs_cached(spring_config, groups, 0, 0, 0, &mut HashMap::new())
 }
 
-fn valid_configs_cached(spring_config: &str, groups: &Vec<usize>, cur_idx: usize, cur_group_idx: usize, cur_group_size: usize, mut cache: &mut HashMap<(usize, usize, usize), usize>) -> usize {
+fn valid_configs_cached(spring_config: &str, groups: &Vec<usize>, cur_idx: usize, cur_group_idx: usize, cur_group_size: usize, mut cache: &mut HashMap<(usize, usize, usize), u128>) -> u128 {
     if let Some(cached_value) = cache.get(&(cur_idx, cur_group_idx, cur_group_size)) {
         return *cached_value;
     }
@@ -51,7 +65,7 @@ fn valid_configs_cached(spring_config: &str, groups: &Vec<usize>, cur_idx: usize
     if cur_idx == spring_config.len() { // reached the end
         return if cur_group_idx == groups.len() && cur_group_size == 0 { // outside a group
             1
-        } else if cur_group_idx == groups.len() - 1 && cur_group_size == groups[cur_group_idx] { // inside last group that exactly matches desired group
+        } else if cur_group_idx + 1 == groups.len() && cur_group_size == groups[cur_group_idx] { // inside last group that exactly matches desired group
             1
         } else { // ended up in invalid state: too many/little groups, or last group incorrect size
             0
@@ -79,9 +93,92 @@ fn valid_configs_cached(spring_config: &str, groups: &Vec<usize>, cur_idx: usize
 }
 
 
+/// All concrete arrangements of a line of springs, with every '?' resolved, in lexicographic order (so '#' before '.').
+/// Arrangements are only constructed when requested, using the counts of [valid_configs_cached] to skip over all
+/// arrangements that come before it.
+struct Arrangements {
+    spring_config: String,
+    groups: Vec<usize>,
+    cache: HashMap<(usize, usize, usize), u128>,
+    total: u128,
+    next: u128,
+}
+
+impl Arrangements {
+    fn new(springs_line: &str) -> Self {
+        let (config_str, groups_str) = split_first(springs_line, ' ').unwrap();
+        let groups = get_numbers::<usize>(groups_str.replace(",", " ").as_str());
+        let mut cache = HashMap::new();
+        let total = valid_configs_cached(config_str, &groups, 0, 0, 0, &mut cache);
+        Arrangements { spring_config: config_str.to_string(), groups, cache, total, next: 0 }
+    }
+
+    fn len(&self) -> u128 {
+        self.total
+    }
+
+    /// The `k`-th arrangement (counting from 0), or `None` if there are not that many arrangements
+    fn get(&mut self, mut k: u128) -> Option<String> {
+        if k >= self.total {
+            return None;
+        }
+        let mut arrangement = String::with_capacity(self.spring_config.len());
+        let (mut group_idx, mut group_size) = (0, 0);
+        for (idx, c) in self.spring_config.chars().enumerate() {
+            let options = if c == '?' { vec!['#', '.'] } else { vec![c] };
+            for option in options {
+                let Some((next_group_idx, next_group_size)) = next_state(&self.groups, group_idx, group_size, option == '#') else { continue };
+                let count = valid_configs_cached(&self.spring_config, &self.groups, idx + 1, next_group_idx, next_group_size, &mut self.cache);
+                if k < count {
+                    arrangement.push(option);
+                    (group_idx, group_size) = (next_group_idx, next_group_size);
+                    break;
+                }
+                k -= count;
+            }
+        }
+        Some(arrangement)
+    }
+}
+
+impl Iterator for Arrangements {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let arrangement = self.get(self.next);
+        self.next += 1;
+        arrangement
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total.saturating_sub(self.next);
+        (usize::try_from(remaining).unwrap_or(usize::MAX), usize::try_from(remaining).ok())
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.next = self.next.saturating_add(n as u128);
+        self.next()
+    }
+}
+
+/// The (group index, group size) after reading a damaged or operational spring, or `None` if that closes a group of
+/// the wrong size
+fn next_state(groups: &[usize], group_idx: usize, group_size: usize, is_damaged: bool) -> Option<(usize, usize)> {
+    if is_damaged {
+        Some((group_idx, group_size + 1))
+    } else if group_size == 0 {
+        Some((group_idx, 0))
+    } else if groups.get(group_idx) == Some(&group_size) {
+        Some((group_idx + 1, 0))
+    } else {
+        None
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
-    use crate::day12::{num_valid_configs, part1, part2, unfold};
+    use crate::day12::{Arrangements, configs_per_line, dominant_lines, num_valid_configs, part1, part2, unfold, unfold_with};
 
     #[test]
     fn correctly_determine_number_of_configurations() {
@@ -134,4 +231,69 @@ mod tests {
     fn example_part2_line2() {
         assert_eq!(part2(".??..??...?##. 1,1,3"), 16384)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_enumerate_arrangements_in_lexicographic_order() {
+        let arrangements = Arrangements::new(".??..??...?##. 1,1,3").collect::<Vec<_>>();
+        assert_eq!(arrangements, vec![
+            ".#...#....###.",
+            ".#....#...###.",
+            "..#..#....###.",
+            "..#...#...###.",
+        ]);
+        assert_eq!(Arrangements::new("?###???????? 3,2,1").count(), 10);
+        assert_eq!(Arrangements::new("#.# 2").next(), None);
+    }
+
+    #[test]
+    fn should_fetch_kth_arrangement_directly() {
+        let mut arrangements = Arrangements::new(&unfold(".??..??...?##. 1,1,3"));
+        assert_eq!(arrangements.len(), 16384);
+        let last = arrangements.get(16383).unwrap();
+        assert!(last.starts_with("..#...#...###."), "Unexpected last arrangement {last}");
+        assert_eq!(arrangements.get(16384), None);
+        assert_eq!(arrangements.nth(16383), Some(last));
+        assert_eq!(arrangements.next(), None);
+    }
+
+    #[test]
+    fn should_sample_arrangements_of_huge_rows() {
+        let line = unfold("?###???????? 3,2,1");
+        let mut arrangements = Arrangements::new(&line);
+        assert_eq!(arrangements.len(), 506250);
+        let (config, groups) = line.split_once(' ').unwrap();
+        for k in [0, 1, 12345, 506249] {
+            let arrangement = arrangements.get(k).unwrap();
+            assert_eq!(arrangement.len(), config.len());
+            assert!(config.chars().zip(arrangement.chars()).all(|(c, a)| c == '?' || c == a));
+            assert_eq!(num_valid_configs(&format!("{arrangement} {groups}")), 1);
+        }
+    }
+
+    #[test]
+    fn should_unfold_with_configurable_copies_and_separator() {
+        assert_eq!(unfold_with(".# 1", 3, "?"), ".#?.#?.# 1,1,1");
+        assert_eq!(unfold_with(".# 1", 2, "."), ".#..# 1,1");
+        assert_eq!(unfold_with(".# 1", 1, "?"), ".# 1");
+    }
+
+    #[test]
+    fn should_return_counts_per_line() {
+        let input = r"???.### 1,1,3
+.??..??...?##. 1,1,3
+?#?#?#?#?#?#?#? 1,3,1,6
+????.#...#... 4,1,1
+????.######..#####. 1,6,5
+?###???????? 3,2,1";
+        assert_eq!(configs_per_line(input, 5, "?"), vec![1, 16384, 1, 16, 2500, 506250]);
+        assert_eq!(dominant_lines(input, 5, "?", 2), vec![(5, 506250), (1, 16384)]);
+    }
+
+    #[test]
+    fn should_count_beyond_u64_for_large_unfold_factors() {
+        let counts = configs_per_line("?###???????? 3,2,1", 25, "?");
+        assert!(counts[0] > u64::MAX as u128, "Expected a huge count, got {}", counts[0]);
+        let growth = (1..=4).map(|copies| configs_per_line("?###???????? 3,2,1", copies, "?")[0]).collect::<Vec<_>>();
+        assert_eq!(growth, vec![10, 150, 2250, 33750]);
+    }
+}
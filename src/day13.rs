@@ -1,6 +1,4 @@
 use std::fs;
-use std::iter::repeat;
-use std::ops::Add;
 
 pub fn main() {
     let input = &fs::read_to_string("./inputs/day13/input.txt").unwrap();
@@ -9,8 +7,8 @@ pub fn main() {
 }
 
 fn part1(input: &str) -> usize {
-    let sum = parse(input).iter().map(|mirrors|
-        find_reflection_number(mirrors, 0).unwrap().0
+    let sum = parse(input).unwrap().iter().map(|pattern|
+        find_reflection_number(pattern, 0).unwrap().0
     ).sum();
 
     println!("Part 1: sum of all reflection numbers is {sum}");
@@ -18,62 +16,167 @@ fn part1(input: &str) -> usize {
 }
 
 fn part2(input: &str) -> usize {
-    let sum: usize = parse(input).into_iter().map(|mirrors|
-        find_reflection_number(&mirrors, 1).unwrap().0
+    let sum: usize = parse(input).unwrap().iter().map(|pattern|
+        find_reflection_number(pattern, 1).unwrap().0
     ).sum();
 
     println!("Part 2: sum of all reflection numbers with smudges removed is {sum}");
     sum
 }
 
-fn parse(input: &str) -> Vec<Vec<Vec<bool>>> {
-    input.lines().fold(vec![vec![]], |mut acc, line| {
-        if line.is_empty() {
-            acc.push(vec![])
-        } else {
-            acc.last_mut().unwrap().push(line.chars().map(|c| c == '#').collect::<Vec<bool>>());
+/// A pattern of ash (0) and rocks (1), stored both per row and per column as bitmasks, where bit `x` of a row is the
+/// cell in column `x` and bit `y` of a column the cell in row `y`.
+#[derive(Eq, PartialEq, Debug)]
+struct Pattern {
+    width: usize,
+    height: usize,
+    rows: Vec<u64>,
+    columns: Vec<u64>,
+}
+
+impl Pattern {
+    fn new(lines: &[&str]) -> Result<Self, String> {
+        let width = lines.first().map_or(0, |line| line.len());
+        let height = lines.len();
+        if width > 64 || height > 64 {
+            return Err(format!("Pattern of {width}x{height} is larger than the supported 64x64"));
         }
-        acc
-    })
+
+        let mut rows = vec![0; height];
+        let mut columns = vec![0; width];
+        for (y, line) in lines.iter().enumerate() {
+            for (x, _) in line.chars().enumerate().filter(|(_, c)| *c == '#') {
+                rows[y] |= 1 << x;
+                columns[x] |= 1 << y;
+            }
+        }
+        Ok(Pattern { width, height, rows, columns })
+    }
+
+    /// Number of cells that need to be flipped to get the given symmetry, or `None` if the pattern can't have it
+    fn smudges_for(&self, symmetry: Symmetry) -> Option<usize> {
+        let is_square = self.width == self.height;
+        // Every mismatching pair of cells shows up twice, while fixing it only takes a single flip
+        let mismatches: u32 = match symmetry {
+            Symmetry::Rotation180 => (0..self.height)
+                .map(|y| (self.rows[y] ^ reverse(self.rows[self.height - 1 - y], self.width)).count_ones())
+                .sum(),
+            Symmetry::MainDiagonal if is_square => (0..self.height)
+                .map(|y| (self.rows[y] ^ self.columns[y]).count_ones())
+                .sum(),
+            Symmetry::AntiDiagonal if is_square => (0..self.height)
+                .map(|y| (self.rows[y] ^ reverse(self.columns[self.width - 1 - y], self.height)).count_ones())
+                .sum(),
+            _ => return None,
+        };
+        Some(mismatches as usize / 2)
+    }
+
+    fn has_symmetry(&self, symmetry: Symmetry, num_smudges: usize) -> bool {
+        self.smudges_for(symmetry) == Some(num_smudges)
+    }
+
+    /// Every horizontal and vertical reflection line that needs exactly `num_smudges` flipped cells, in the order
+    /// `find_reflection_number` tries them
+    fn reflections(&self, num_smudges: usize) -> Vec<Reflection> {
+        let horizontal = (0..self.height.saturating_sub(1))
+            .map(|y| Reflection { axis: Axis::Horizontal, index: y, smudges: mismatches(&self.rows, y) });
+        let vertical = (0..self.width.saturating_sub(1))
+            .map(|x| Reflection {
+                axis: Axis::Vertical,
+                index: x,
+                smudges: mismatches(&self.columns, x).into_iter().map(|(x, y)| (y, x)).collect(),
+            });
+        horizontal.chain(vertical)
+            .filter(|reflection| reflection.smudges.len() == num_smudges)
+            .collect()
+    }
 }
 
-fn transpose(mirrors: &Vec<Vec<bool>>) -> Vec<Vec<bool>> {
-    let width = mirrors.first().unwrap().len();
-    let height = mirrors.len();
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Axis {
+    /// Reflection line between two rows
+    Horizontal,
+    /// Reflection line between two columns
+    Vertical,
+}
 
-    let mut out: Vec<Vec<bool>> = vec![];
-    repeat(vec![]).take(width).for_each(|new_rows| out.push(new_rows));
+/// A reflection line right after row or column `index`, together with the (x, y) coordinates of the cells that have to
+/// be flipped for it to be a perfect reflection. Of each mismatching pair, the cell above or left of the line is used.
+#[derive(Eq, PartialEq, Debug, Clone)]
+struct Reflection {
+    axis: Axis,
+    index: usize,
+    smudges: Vec<(usize, usize)>,
+}
 
-    for x in 0..width {
-        for y in 0..height {
-            out.get_mut(x).unwrap().insert(y, mirrors[y][x])
+impl Reflection {
+    fn number(&self) -> usize {
+        match self.axis {
+            Axis::Horizontal => 100 * (self.index + 1),
+            Axis::Vertical => self.index + 1,
         }
     }
-    out
 }
 
-fn find_reflection_number(mirrors: &Vec<Vec<bool>>, num_difference: usize) -> Option<(usize, bool, usize)> {
-    if let Some(value) = find_horizontal_reflection_index(mirrors, num_difference) {
+/// The (bit, line) positions that differ between the lines mirrored in the line after `index`, on the near side
+fn mismatches(lines: &[u64], index: usize) -> Vec<(usize, usize)> {
+    (0..=index).rev().zip(index + 1..lines.len())
+        .flat_map(|(near, far)| {
+            let difference = lines[near] ^ lines[far];
+            (0..64).filter(move |bit| difference & (1 << bit) != 0).map(move |bit| (bit, near))
+        })
+        .collect()
+}
+
+/// Symmetries of a pattern other than reflection in a horizontal or vertical line
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum Symmetry {
+    Rotation180,
+    /// Reflection in the diagonal from the top-left to the bottom-right corner, only for square patterns
+    MainDiagonal,
+    /// Reflection in the diagonal from the top-right to the bottom-left corner, only for square patterns
+    AntiDiagonal,
+}
+
+/// Reverses the lowest `length` bits
+fn reverse(bits: u64, length: usize) -> u64 {
+    if length == 0 { 0 } else { bits.reverse_bits() >> (64 - length) }
+}
+
+fn parse(input: &str) -> Result<Vec<Pattern>, String> {
+    input.lines().fold(vec![vec![]], |mut acc: Vec<Vec<&str>>, line| {
+        if line.is_empty() {
+            acc.push(vec![])
+        } else {
+            acc.last_mut().unwrap().push(line);
+        }
+        acc
+    }).iter().map(|lines| Pattern::new(lines)).collect()
+}
+
+fn find_reflection_number(pattern: &Pattern, num_difference: usize) -> Option<(usize, bool, usize)> {
+    if let Some(value) = find_horizontal_reflection_index(&pattern.rows, num_difference) {
         return Some((100 * (value + 1), true, value));
-    } else if let Some(value) = find_horizontal_reflection_index(&transpose(mirrors), num_difference) {
+    } else if let Some(value) = find_horizontal_reflection_index(&pattern.columns, num_difference) {
         return Some((value + 1, false, value));
     }
     None
 }
 
-fn find_horizontal_reflection_index(mirrors: &Vec<Vec<bool>>, num_differences: usize) -> Option<usize> {
-    for y in 0..mirrors.len() - 1 {
+fn find_horizontal_reflection_index(lines: &[u64], num_differences: usize) -> Option<usize> {
+    for y in 0..lines.len().saturating_sub(1) {
         let mut total_error = 0;
         let mut offset = 0;
 
         while offset <= y
-            && (y + offset + 1) < mirrors.len()
+            && (y + offset + 1) < lines.len()
             && total_error <= num_differences
         {
             // The trick here is to just count the number of differences between the reflections
             // For part 1 we want equality, so number of differences should be 0. But in part 2 we
             // want to flip exactly one entry, so we look at that instead
-            total_error += num_elements_unequal(&mirrors[y - offset], &mirrors[y + offset + 1]);
+            total_error += (lines[y - offset] ^ lines[y + offset + 1]).count_ones() as usize;
             offset += 1;
         }
 
@@ -84,15 +187,10 @@ fn find_horizontal_reflection_index(mirrors: &Vec<Vec<bool>>, num_differences: u
     None
 }
 
-fn num_elements_unequal(a: &Vec<bool>, b: &Vec<bool>) -> usize {
-    a.iter().zip(b).filter(|(ai, bi)| ai != bi).count()
-}
-
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
-    use crate::day13::{part1, part2};
+    use crate::day13::{Axis, parse, part1, part2, Pattern, Reflection, Symmetry};
 
     #[test]
     fn correctly_determine_reflection_number_horizontally() {
@@ -231,4 +329,103 @@ mod tests {
 #..#..#..#..##...";
         assert_eq!(part2(input), 5)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_encode_pattern_as_row_and_column_bitmasks() {
+        let pattern = Pattern::new(&["#..", "##."]).unwrap();
+        assert_eq!(pattern, Pattern { width: 3, height: 2, rows: vec![0b001, 0b011], columns: vec![0b11, 0b10, 0b00] });
+    }
+
+    #[test]
+    fn should_detect_rotational_symmetry() {
+        let pattern = &parse(".#.\n.#.\n.##").unwrap()[0];
+        assert!(!pattern.has_symmetry(Symmetry::Rotation180, 0));
+        assert_eq!(pattern.smudges_for(Symmetry::Rotation180), Some(1));
+
+        let pattern = &parse("##.\n.#.\n.##").unwrap()[0];
+        assert!(pattern.has_symmetry(Symmetry::Rotation180, 0));
+    }
+
+    #[test]
+    fn should_detect_diagonal_symmetry_on_square_patterns() {
+        let pattern = &parse("#.#\n.#.\n#..").unwrap()[0];
+        assert!(pattern.has_symmetry(Symmetry::MainDiagonal, 0));
+        assert!(pattern.has_symmetry(Symmetry::AntiDiagonal, 1));
+
+        let pattern = &parse("##.\n#.#").unwrap()[0];
+        assert_eq!(pattern.smudges_for(Symmetry::MainDiagonal), None);
+        assert_eq!(pattern.smudges_for(Symmetry::AntiDiagonal), None);
+    }
+
+    #[test]
+    fn should_list_all_reflections_with_their_smudges() {
+        let pattern = &parse(r"#.##..##.
+..#.##.#.
+##......#
+##......#
+..#.##.#.
+..##..##.
+#.#.##.#.").unwrap()[0];
+        assert_eq!(pattern.reflections(0), vec![Reflection { axis: Axis::Vertical, index: 4, smudges: vec![] }]);
+        assert_eq!(pattern.reflections(1), vec![Reflection { axis: Axis::Horizontal, index: 2, smudges: vec![(0, 0)] }]);
+    }
+
+    #[test]
+    fn should_explain_smudges_of_regression_patterns() {
+        // In all three the original reflection line remains a perfect one, so it must not be counted in part 2
+        let bug1 = &parse(r"#.#.....#.##.##.#
+#.#.....#.##.##.#
+.....#...#####...
+...#.###....#...#
+.###..####.#..#.#
+#.###.#.#..###..#
+..####...##.#.##.
+..####...##.#.##.
+#.#.#.#.#..###..#
+.###..####.#..#.#
+...#.###....#...#
+.....#...#####...
+#.#.....#.##.##.#").unwrap()[0];
+        assert_eq!(bug1.reflections(0), vec![Reflection { axis: Axis::Horizontal, index: 0, smudges: vec![] }]);
+        assert_eq!(bug1.reflections(1), vec![Reflection { axis: Axis::Horizontal, index: 6, smudges: vec![(3, 5)] }]);
+
+        let bug2 = &parse(r".##.#..
+.##.###
+..#####
+#..####
+.####..
+.#...##
+##...##").unwrap()[0];
+        assert_eq!(bug2.reflections(0), vec![Reflection { axis: Axis::Vertical, index: 5, smudges: vec![] }]);
+        assert_eq!(bug2.reflections(1), vec![Reflection { axis: Axis::Horizontal, index: 5, smudges: vec![(0, 5)] }]);
+
+        // The only smudged line is vertical, so all horizontal lines have to be rejected first
+        let bug3 = &parse(r".#......#..##.###
+.###..###.##.####
+.##....##.#####.#
+#.######.##...##.
+##.#..#.##.###..#
+#...##...#.#.###.
+..##..##.........
+#..####.###....#.
+.#.####.#.#####..
+.##....##.##..##.
+###.##.####....##
+#..#..#..#..##...
+#..####..#####...
+#..####..#####...
+#..#..#..#..##...").unwrap()[0];
+        assert_eq!(bug3.reflections(0), vec![Reflection { axis: Axis::Horizontal, index: 12, smudges: vec![] }]);
+        let smudged = bug3.reflections(1);
+        assert_eq!(smudged, vec![Reflection { axis: Axis::Vertical, index: 4, smudges: vec![(1, 7)] }]);
+        assert_eq!(smudged[0].number(), 5);
+    }
+
+    #[test]
+    fn should_reject_patterns_larger_than_64x64() {
+        let wide = ".".repeat(65);
+        assert!(Pattern::new(&[&wide]).is_err());
+        assert!(parse(&"#\n".repeat(65)).is_err());
+        assert!(Pattern::new(&[&wide[1..]]).is_ok());
+    }
+}
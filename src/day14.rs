@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use itertools::Itertools;
+
+use crate::common::Direction;
+use crate::common::Direction::{East, North, South, West};
 
 pub fn main() {
     let input = &fs::read_to_string("./inputs/day14/input.txt").unwrap();
@@ -8,9 +12,9 @@ pub fn main() {
 }
 
 fn part1(input: &str) -> usize {
-    let mut rocks = parse(input);
-    roll_north(&mut rocks);
-    let load = total_load_north(&rocks);
+    let mut platform = Platform::new(input);
+    platform.run(&TiltProgram::parse("N").unwrap());
+    let load = platform.load(North);
 
     println!("Part 1: total load on the north side is {load}");
     load
@@ -18,161 +22,287 @@ fn part1(input: &str) -> usize {
 
 fn part2(input: &str) -> usize {
     let total_cycles = 1000000000;
-    let initial_cycles = 100;
-    let second_cycles = 300;
-
-    let mut rocks = parse(input);
-
-    // cycle for a while to reach a steady state
-    for _ in 0..initial_cycles {
-        cycle(&mut rocks);
-    }
-
-    // keep a log of the state after each cycle, with this we can find the frequency
-    let mut states = vec![];
-    for i in 0..second_cycles {
-        cycle(&mut rocks);
-        states.push(rocks.clone())
-    }
-
-    // Crude way to find the frequency: take the last, then check which states are exactly equal
-    let last = states.last().unwrap();
-    let equilibrium = states.iter().map(|state| state == last).collect::<Vec<_>>();
-    // Then using the difference of indices we can determine the wave-length
-    let wave_length = equilibrium.iter().enumerate().filter(|(idx, b)| **b)
-        .tuple_windows().map(|(a,b)| b.0 - a.0)
-        .collect::<Vec<_>>();
-    let wave_length_guess = wave_length.last().unwrap();
-    if wave_length.iter().all(|f| f == wave_length_guess) {
-        //println!("Wave length is {wave_length_guess}");
-    } else {
-        // If we were to reach this, we would need to tweak the number of cycles we test for
-        panic!("No wave-length found!");
-    }
-
-    // Now it's just some modular arithmetic to find how often we need to cycle to get in final state
-    let cycles_left = total_cycles - initial_cycles - second_cycles;
-    let remainder = cycles_left % wave_length_guess;
-    for i in 0..remainder {
-        cycle(&mut rocks);
-    }
-    // rocks.iter().for_each(|row| {
-    //     row.iter().for_each(|c| print!("{c}"));
-    //     println!();
-    // });
-    let load = total_load_north(&rocks);
+    let mut platform = Platform::new(input);
+    platform.run(&TiltProgram::parse(&format!("NWSE*{total_cycles}")).unwrap());
+
+    let load = platform.load(North);
     println!("Part 2: after {total_cycles} cycles load on north is {load}");
     load
 }
-fn parse(input: &str) -> Vec<Vec<char>> {
-    input.lines().map(|line| line.chars().collect()).collect()
-}
 
-fn set(matrix: &mut Vec<Vec<char>>, (x, y): (usize, usize), value: char) {
-    let mut row = matrix.get_mut(y).unwrap();
-    row[x] = value
+/// The platform as bitboards of the round rocks, either per row or per column depending on the last tilt. Tilting fills
+/// every stretch between cube rocks of a row or column with as many rocks as it had, counted by popcount, so it touches
+/// every word once plus once per stretch. Switching between rows and columns transposes the board in 64x64 blocks.
+#[derive(Clone, Debug)]
+struct Platform {
+    width: usize,
+    height: usize,
+    /// The round rocks per row (`width` bits per line) or per column (`height` bits per line)
+    round: BitGrid,
+    by_columns: bool,
+    cube: BitGrid,
+    /// For every row the stretches of cells not covered by cube rocks, as `(start, end)`
+    row_segments: Vec<Vec<(usize, usize)>>,
+    /// For every column the stretches of cells not covered by cube rocks, as `(start, end)`
+    column_segments: Vec<Vec<(usize, usize)>>,
 }
 
-fn total_load_north(matrix: &Vec<Vec<char>>) -> usize {
-    matrix.iter().rev().enumerate().fold(0, |acc, (y, row)| {
-        acc + (y+1) *  row.iter().filter(|&c| c == &'O').count()
-    })
-}
+impl Platform {
+    fn new(input: &str) -> Self {
+        let lines = input.lines().collect::<Vec<_>>();
+        let width = lines.first().map_or(0, |line| line.len());
+        let height = lines.len();
 
-fn cycle(rocks: &mut Vec<Vec<char>>){
-    // sometimes copy pasting and tweaking a few things is quicker than trying to be smart
-    roll_north(rocks);
-    roll_west(rocks);
-    roll_south(rocks);
-    roll_east(rocks);
-}
-
-fn roll_north(matrix: &mut Vec<Vec<char>>){
-    for y in 0..matrix.len() {
-        for x in 0..matrix[0].len() {
-            if matrix[y][x] == 'O' {
-                let mut new_y = y;
-                for shift in 1..=y {
-                    match matrix[y - shift][x] {
-                        '.' => new_y = y-shift,
-                        _ => break
-                    }
-                }
-                if new_y != y {
-                    set(matrix, (x, new_y), 'O');
-                    set(matrix, (x, y), '.');
+        let mut round = BitGrid::new(height, width);
+        let mut cube = BitGrid::new(height, width);
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                match c {
+                    'O' => round.set(y, x),
+                    '#' => cube.set(y, x),
+                    _ => {}
                 }
             }
         }
+        let row_segments = (0..height).map(|y| free_segments(&cube, y)).collect();
+        let cube_columns = cube.transposed();
+        let column_segments = (0..width).map(|x| free_segments(&cube_columns, x)).collect();
+        Platform { width, height, round, by_columns: false, cube, row_segments, column_segments }
     }
-}
 
-fn roll_south(matrix: &mut Vec<Vec<char>>){
-    for y in (0..matrix.len()).rev() {
-        for x in 0..matrix[0].len() {
-            if matrix[y][x] == 'O' {
-                let mut new_y = y;
-                for try_y in (y+1)..matrix.len() {
-                    match matrix[try_y][x] {
-                        '.' => new_y = try_y,
-                        _ => break
-                    }
+    /// The positions of the round rocks per row, which is all that changes when tilting
+    fn state(&self) -> BitGrid {
+        if self.by_columns { self.round.transposed() } else { self.round.clone() }
+    }
+
+    fn tilt(&mut self, direction: Direction) {
+        let vertical = matches!(direction, North | South);
+        if vertical != self.by_columns {
+            self.round = self.round.transposed();
+            self.by_columns = vertical;
+        }
+        let segments = if vertical { &self.column_segments } else { &self.row_segments };
+        // North and west are towards the start of a line
+        let towards_start = matches!(direction, North | West);
+        for (line, segments) in segments.iter().enumerate() {
+            let counts = segments.iter().map(|(start, end)| self.round.count_ones(line, *start, *end)).collect::<Vec<_>>();
+            self.round.clear_line(line);
+            for ((start, end), count) in segments.iter().zip(counts) {
+                if towards_start {
+                    self.round.fill(line, *start, start + count);
+                } else {
+                    self.round.fill(line, end - count, *end);
                 }
-                if new_y != y {
-                    set(matrix, (x, new_y), 'O');
-                    set(matrix, (x, y), '.');
+            }
+        }
+    }
+
+    fn run(&mut self, program: &TiltProgram) {
+        for (directions, times) in program.steps.iter() {
+            self.repeat(directions, *times);
+        }
+    }
+
+    /// Tilts in all `directions` in turn, `times` times over, skipping ahead once a state repeats
+    fn repeat(&mut self, directions: &[Direction], times: usize) {
+        let mut seen: HashMap<BitGrid, usize> = HashMap::new();
+        let mut done = 0;
+        while done < times {
+            if let Some(previous) = seen.insert(self.state(), done) {
+                let remaining = (times - done) % (done - previous);
+                for _ in 0..remaining {
+                    directions.iter().for_each(|direction| self.tilt(*direction));
                 }
+                return;
             }
+            directions.iter().for_each(|direction| self.tilt(*direction));
+            done += 1;
         }
     }
+
+    /// The load on the given side: every round rock counts its distance to the opposite edge, plus one
+    fn load(&self, side: Direction) -> usize {
+        let rows = self.state();
+        (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|(x, y)| rows.get(*y, *x))
+            .map(|(x, y)| match side {
+                North => self.height - y,
+                South => y + 1,
+                West => self.width - x,
+                East => x + 1,
+            })
+            .sum()
+    }
 }
 
-fn roll_west(matrix: &mut Vec<Vec<char>>){
-    for x in 0..matrix[0].len() {
-        for y in 0..matrix.len() {
-            if matrix[y][x] == 'O' {
-                let mut new_x = x;
-                for shift in 1..=x {
-                    match matrix[y][x-shift] {
-                        '.' => new_x = x - shift,
-                        _ => break
-                    }
-                }
-                if new_x != x {
-                    set(matrix, (new_x, y), 'O');
-                    set(matrix, (x, y), '.');
-                }
+/// A sequence of tilts, written as groups of directions that are each repeated a number of times, e.g. `"N"`, `"SSEE"`
+/// or `"NWSE*1000000000"`. Groups are separated by whitespace, as in `"N*3 WE"`.
+#[derive(Eq, PartialEq, Debug)]
+struct TiltProgram {
+    steps: Vec<(Vec<Direction>, usize)>,
+}
+
+impl TiltProgram {
+    fn parse(program: &str) -> Result<TiltProgram, String> {
+        let steps = program.split_whitespace().map(|group| {
+            let (directions, times) = match group.split_once('*') {
+                Some((directions, times)) => (directions, times.parse::<usize>()
+                    .map_err(|err| format!("Invalid repetition '{times}' in '{group}': {err}"))?),
+                None => (group, 1),
+            };
+            let directions = directions.chars().map(|c| match c {
+                'N' => Ok(North),
+                'E' => Ok(East),
+                'S' => Ok(South),
+                'W' => Ok(West),
+                _ => Err(format!("Unknown direction '{c}' in '{group}'")),
+            }).collect::<Result<Vec<_>, _>>()?;
+            if directions.is_empty() {
+                return Err(format!("No directions to repeat in '{group}'"));
             }
+            Ok((directions, times))
+        }).collect::<Result<Vec<_>, String>>()?;
+        Ok(TiltProgram { steps })
+    }
+}
+
+/// The stretches of unset bits in the line, as `(start, end)`
+fn free_segments(cubes: &BitGrid, line: usize) -> Vec<(usize, usize)> {
+    let mut segments = vec![];
+    let mut start = 0;
+    for idx in 0..=cubes.length {
+        if idx == cubes.length || cubes.get(line, idx) {
+            if start < idx {
+                segments.push((start, idx));
+            }
+            start = idx + 1;
         }
     }
+    segments
 }
 
-fn roll_east(matrix: &mut Vec<Vec<char>>){
-    let width = matrix[0].len();
-    for x in (0..width).rev() {
-        for y in 0..matrix.len() {
-            if matrix[y][x] == 'O' {
-                let mut new_x = x;
-                for try_x in (x+1..width) {
-                    match matrix[y][try_x] {
-                        '.' => new_x = try_x,
-                        _ => break
+/// A number of lines of `length` bits, where bit `idx` of a line is bit `idx % 64` of its word `idx / 64`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct BitGrid {
+    lines: usize,
+    length: usize,
+    words_per_line: usize,
+    words: Vec<u64>,
+}
+
+impl BitGrid {
+    fn new(lines: usize, length: usize) -> Self {
+        let words_per_line = length.div_ceil(64);
+        BitGrid { lines, length, words_per_line, words: vec![0; lines * words_per_line] }
+    }
+
+    fn get(&self, line: usize, idx: usize) -> bool {
+        self.words[line * self.words_per_line + idx / 64] >> (idx % 64) & 1 == 1
+    }
+
+    fn set(&mut self, line: usize, idx: usize) {
+        self.words[line * self.words_per_line + idx / 64] |= 1 << (idx % 64);
+    }
+
+    fn clear_line(&mut self, line: usize) {
+        self.words[line * self.words_per_line..(line + 1) * self.words_per_line].fill(0);
+    }
+
+    /// The words of the line overlapping `start..end`, with the mask of the bits within the range
+    fn range_masks(&self, line: usize, start: usize, end: usize) -> impl Iterator<Item=(usize, u64)> {
+        let offset = line * self.words_per_line;
+        (start / 64..end.div_ceil(64)).map(move |word| {
+            let low = start.max(word * 64) - word * 64;
+            let high = end.min(word * 64 + 64) - word * 64;
+            let mask = if high - low == 64 { u64::MAX } else { ((1 << (high - low)) - 1) << low };
+            (offset + word, mask)
+        })
+    }
+
+    fn count_ones(&self, line: usize, start: usize, end: usize) -> usize {
+        self.range_masks(line, start, end).map(|(word, mask)| (self.words[word] & mask).count_ones() as usize).sum()
+    }
+
+    fn fill(&mut self, line: usize, start: usize, end: usize) {
+        for (word, mask) in self.range_masks(line, start, end).collect::<Vec<_>>() {
+            self.words[word] |= mask;
+        }
+    }
+
+    /// Swaps lines and bits, a 64x64 block at a time
+    fn transposed(&self) -> BitGrid {
+        let mut transposed = BitGrid::new(self.length, self.lines);
+        for line_block in 0..self.lines.div_ceil(64) {
+            for word in 0..self.words_per_line {
+                let mut block = [0u64; 64];
+                for (i, row) in block.iter_mut().enumerate() {
+                    let line = line_block * 64 + i;
+                    if line < self.lines {
+                        *row = self.words[line * self.words_per_line + word];
                     }
                 }
-                if new_x != x {
-                    set(matrix, (new_x, y), 'O');
-                    set(matrix, (x, y), '.');
+                transpose_block(&mut block);
+                for (i, row) in block.iter().enumerate() {
+                    let line = word * 64 + i;
+                    if line < transposed.lines {
+                        transposed.words[line * transposed.words_per_line + line_block] = *row;
+                    }
                 }
             }
         }
+        transposed
+    }
+}
+
+/// Transposes a 64x64 bit matrix in place, where bit `j` of `block[i]` is entry (i, j), by swapping ever smaller
+/// off-diagonal blocks
+fn transpose_block(block: &mut [u64; 64]) {
+    let mut size = 32;
+    let mut mask: u64 = 0x0000_0000_FFFF_FFFF;
+    while size != 0 {
+        let mut i = 0;
+        while i < 64 {
+            let swap = ((block[i] >> size) ^ block[i + size]) & mask;
+            block[i] ^= swap << size;
+            block[i + size] ^= swap;
+            i = (i + size + 1) & !size;
+        }
+        size >>= 1;
+        mask ^= mask << size;
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let round = self.state();
+        for y in 0..self.height {
+            let row = (0..self.width).map(|x| match (round.get(y, x), self.cube.get(y, x)) {
+                (true, _) => 'O',
+                (_, true) => '#',
+                _ => '.',
+            }).collect::<String>();
+            writeln!(f, "{row}")?;
+        }
+        Ok(())
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
-    use crate::day14::{part1, part2};
+    use crate::common::Direction::{East, North, South, West};
+    use crate::day14::{BitGrid, part1, part2, Platform, TiltProgram};
+
+    const EXAMPLE: &str = r"O....#....
+O.OO#....#
+.....##...
+OO.#O....O
+.O.....O#.
+O.#..O.#.#
+..O..#O..O
+.......O..
+#....###..
+#OO..#....";
 
     #[test]
     fn part_1_example_roll_north_and_count_load() {
@@ -204,4 +334,143 @@ O.#..O.#.#
         assert_eq!(part2(input), 64)
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn should_tilt_north() {
+        let mut platform = Platform::new(EXAMPLE);
+        platform.tilt(North);
+        assert_eq!(platform.to_string(), r"OOOO.#.O..
+OO..#....#
+OO..O##..O
+O..#.OO...
+........#.
+..#....#.#
+..O..#.O.O
+..O.......
+#....###..
+#....#....
+");
+    }
+
+    #[test]
+    fn should_match_example_spin_cycles() {
+        let mut platform = Platform::new(EXAMPLE);
+        platform.run(&TiltProgram::parse("NWSE").unwrap());
+        assert_eq!(platform.to_string(), r".....#....
+....#...O#
+...OO##...
+.OO#......
+.....OOO#.
+.O#...O#.#
+....O#....
+......OOOO
+#...O###..
+#..OO#....
+");
+        platform.run(&TiltProgram::parse("NWSE*2").unwrap());
+        assert_eq!(platform.to_string(), r".....#....
+....#...O#
+.....##...
+..O#......
+.....OOO#.
+.O#...O#.#
+....O#...O
+.......OOO
+#...O###.O
+#.OOO#...O
+");
+    }
+
+    #[test]
+    fn tilting_should_keep_rocks_and_be_idempotent() {
+        for direction in [North, East, South, West] {
+            let mut platform = Platform::new(EXAMPLE);
+            platform.tilt(direction);
+            let tilted = platform.to_string();
+            assert_eq!(tilted.matches('O').count(), EXAMPLE.matches('O').count());
+            platform.tilt(direction);
+            assert_eq!(platform.to_string(), tilted, "Tilting {direction:?} twice should not move rocks");
+        }
+    }
+
+    #[test]
+    fn skipping_cycles_should_match_simulating_them() {
+        for total in [1, 9, 10, 27] {
+            let mut simulated = Platform::new(EXAMPLE);
+            for _ in 0..total {
+                [North, West, South, East].iter().for_each(|direction| simulated.tilt(*direction));
+            }
+            let mut skipped = Platform::new(EXAMPLE);
+            skipped.repeat(&[North, West, South, East], total);
+            assert_eq!(skipped.state(), simulated.state(), "Wrong state after {total} cycles");
+        }
+    }
+
+    #[test]
+    fn should_parse_tilt_programs() {
+        assert_eq!(TiltProgram::parse("SSEE"), Ok(TiltProgram { steps: vec![(vec![South, South, East, East], 1)] }));
+        assert_eq!(
+            TiltProgram::parse("N*3 WE"),
+            Ok(TiltProgram { steps: vec![(vec![North], 3), (vec![West, East], 1)] })
+        );
+        assert_eq!(TiltProgram::parse("NX"), Err("Unknown direction 'X' in 'NX'".to_string()));
+        assert!(TiltProgram::parse("*4").is_err());
+        assert!(TiltProgram::parse("N*many").is_err());
+    }
+
+    #[test]
+    fn should_measure_load_on_each_side_after_tilting_that_way() {
+        // Tilting towards a side puts every rock as close to it as possible, so the load on that side only goes up
+        for (program, side, expected) in [("N", North, 136), ("S", South, 132), ("E", East, 105), ("W", West, 147)] {
+            let mut platform = Platform::new(EXAMPLE);
+            let before = platform.load(side);
+            platform.run(&TiltProgram::parse(program).unwrap());
+            assert!(platform.load(side) >= before);
+            assert_eq!(platform.load(side), expected, "Wrong load on {side:?} after '{program}'");
+        }
+    }
+
+    #[test]
+    fn repeating_a_tilt_should_not_change_anything() {
+        let mut once = Platform::new(EXAMPLE);
+        once.run(&TiltProgram::parse("SE").unwrap());
+        let mut twice = Platform::new(EXAMPLE);
+        twice.run(&TiltProgram::parse("SSEE").unwrap());
+        assert_eq!(once.state(), twice.state());
+    }
+
+    #[test]
+    fn should_run_billion_cycles_as_program() {
+        let mut platform = Platform::new(EXAMPLE);
+        platform.run(&TiltProgram::parse("NWSE*1000000000").unwrap());
+        assert_eq!(platform.load(North), 64);
+    }
+
+    #[test]
+    fn should_transpose_grids_spanning_several_words() {
+        let mut grid = BitGrid::new(70, 130);
+        for (line, idx) in [(0, 0), (0, 129), (5, 64), (69, 63), (69, 128), (33, 77)] {
+            grid.set(line, idx);
+        }
+        let transposed = grid.transposed();
+        assert_eq!((transposed.lines, transposed.length), (130, 70));
+        for line in 0..70 {
+            for idx in 0..130 {
+                assert_eq!(transposed.get(idx, line), grid.get(line, idx), "Wrong bit at ({line}, {idx})");
+            }
+        }
+        assert_eq!(transposed.transposed(), grid);
+    }
+
+    #[test]
+    fn should_tilt_platforms_wider_than_a_word() {
+        let row = format!("{}O#{}O", ".".repeat(100), ".".repeat(60));
+        let mut platform = Platform::new(&format!("{row}\n{row}"));
+        platform.run(&TiltProgram::parse("W").unwrap());
+        let tilted = format!("O{}#O{}", ".".repeat(100), ".".repeat(60));
+        assert_eq!(platform.to_string(), format!("{tilted}\n{tilted}\n"));
+        platform.run(&TiltProgram::parse("SE").unwrap());
+        assert_eq!(platform.load(East), 2 * (101 + 163));
+        // Both columns with rocks are now full, which counts 2 on the top row and 1 on the bottom one
+        assert_eq!(platform.load(North), 2 * (2 + 1));
+    }
+}
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
 use std::fs;
-use itertools::Itertools;
 
 pub fn main() {
     let input = &fs::read_to_string("./inputs/day15/input.txt").unwrap();
@@ -15,51 +16,151 @@ fn part1(input: &str) -> usize {
 }
 
 fn part2(input: &str) -> usize {
-    let mut boxes = HashMap::new();
-    parse(input, &mut boxes);
-    let score = boxes.iter().map(|(k, v)| -> usize{
-        (k + 1) * v.iter().enumerate().map(|(idx, lens)| (idx + 1) * lens.focal_length).sum::<usize>()
-    })
-        .sum();
+    let steps = parse(input).unwrap();
+    let score = LensLibrary::replay(&steps).focusing_power();
     println!("Part 2: score is {score}");
     score
 }
 
 fn hash(something: &str) -> usize {
-    something.to_string().bytes().fold(0, |hash, c| ((hash + c as usize) * 17) % 256)
-}
-
-fn parse(input: &str, state: &mut HashMap<usize, Vec<Lens>>) {
-    input.split(',').for_each(|section| {
-        if section.contains('=') {  // insert into or update a box
-            let (label, focal_length) = section.split_at(section.find("=").unwrap());
-            let box_number = hash(label);
-            let lens = Lens { label: label.to_string(), focal_length: focal_length[1..].parse().unwrap() };
-
-            if let Some(content) = state.get_mut(&box_number) {
-                let idx = content.iter().position(|lens| lens.label == label);
-                if idx.is_some() {  // lens with same label already in box, update focal length
-                    content[idx.unwrap()].focal_length = lens.focal_length
-                } else {
-                    content.push(lens);
-                }
-            } else {  // box doesn't exist yet
-                state.insert(box_number, vec![lens]);
-            }
-        } else {  // remove lens from box
-            let label = section.split('-').next().unwrap();
-            let box_number = hash(label);
-
-            state.get_mut(&box_number)
-                .map(|content| {
-                    content.iter().position(|lens| lens.label == label)
-                        .map(|idx| content.remove(idx))
-                });
+    Holiday::standard().hash(something)
+}
+
+/// Assigns labels to one of a fixed number of boxes
+trait LabelHasher {
+    fn buckets(&self) -> usize;
+    fn hash(&self, label: &str) -> usize;
+}
+
+/// The Holiday ASCII String Helper algorithm: add the character code, multiply, take the remainder
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Holiday {
+    multiplier: usize,
+    modulus: usize,
+}
+
+impl Holiday {
+    fn new(multiplier: usize, modulus: usize) -> Result<Self, String> {
+        Ok(Holiday { multiplier, modulus: nonzero_modulus(modulus)? })
+    }
+
+    fn standard() -> Self {
+        Holiday { multiplier: 17, modulus: 256 }
+    }
+}
+
+impl LabelHasher for Holiday {
+    fn buckets(&self) -> usize {
+        self.modulus
+    }
+
+    fn hash(&self, label: &str) -> usize {
+        // Both factors are below the modulus, so their product fits in a u128 whatever the parameters
+        let modulus = self.modulus as u128;
+        let multiplier = self.multiplier as u128 % modulus;
+        label.bytes().fold(0, |hash, c| (hash + c as u128) % modulus * multiplier % modulus) as usize
+    }
+}
+
+const FNV_OFFSET_BASIS: u32 = 2166136261;
+const FNV_PRIME: u32 = 16777619;
+
+fn nonzero_modulus(modulus: usize) -> Result<usize, String> {
+    if modulus == 0 { Err("Labels can't be hashed into 0 boxes".to_string()) } else { Ok(modulus) }
+}
+
+/// 32-bit FNV-1 (multiply, then xor the byte), reduced to `modulus` buckets
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Fnv1 {
+    modulus: usize,
+}
+
+impl Fnv1 {
+    fn new(modulus: usize) -> Result<Self, String> {
+        Ok(Fnv1 { modulus: nonzero_modulus(modulus)? })
+    }
+}
+
+impl LabelHasher for Fnv1 {
+    fn buckets(&self) -> usize {
+        self.modulus
+    }
+
+    fn hash(&self, label: &str) -> usize {
+        let hash = label.bytes().fold(FNV_OFFSET_BASIS, |hash, c| hash.wrapping_mul(FNV_PRIME) ^ c as u32);
+        hash as usize % self.modulus
+    }
+}
+
+/// 32-bit FNV-1a (xor the byte, then multiply), reduced to `modulus` buckets
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Fnv1a {
+    modulus: usize,
+}
+
+impl Fnv1a {
+    fn new(modulus: usize) -> Result<Self, String> {
+        Ok(Fnv1a { modulus: nonzero_modulus(modulus)? })
+    }
+}
+
+impl LabelHasher for Fnv1a {
+    fn buckets(&self) -> usize {
+        self.modulus
+    }
+
+    fn hash(&self, label: &str) -> usize {
+        let hash = label.bytes().fold(FNV_OFFSET_BASIS, |hash, c| (hash ^ c as u32).wrapping_mul(FNV_PRIME));
+        hash as usize % self.modulus
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Step>, String> {
+    input.trim().split(',').enumerate()
+        .map(|(idx, section)| Step::parse(section).map_err(|err| format!("Step {}: {err}", idx + 1)))
+        .collect()
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+enum Step {
+    /// Puts a lens with the label in its box, or replaces the focal length if one is already there
+    Set { label: String, focal: usize },
+    /// Takes the lens with the label out of its box, if it is there
+    Remove { label: String },
+}
+
+impl Step {
+    fn parse(section: &str) -> Result<Step, String> {
+        let (label, step) = if let Some((label, focal)) = section.split_once('=') {
+            let focal = focal.parse::<usize>().ok().filter(|focal| (1..=9).contains(focal))
+                .ok_or(format!("Focal length in '{section}' should be a digit from 1 to 9"))?;
+            (label, Step::Set { label: label.to_string(), focal })
+        } else if let Some(label) = section.strip_suffix('-') {
+            (label, Step::Remove { label: label.to_string() })
+        } else {
+            return Err(format!("'{section}' should be of the form 'label=focal' or 'label-'"));
+        };
+
+        if label.is_empty() || !label.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(format!("Label in '{section}' should consist of lowercase letters"));
         }
-        // println!("After {section}:");
-        // state.iter().for_each(|(k, v)| println!("Box {k}: {:?}", v));
-        // println!()
-    });
+        Ok(step)
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Step::Set { label, .. } | Step::Remove { label } => label,
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Set { label, focal } => write!(f, "{label}={focal}"),
+            Step::Remove { label } => write!(f, "{label}-"),
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -68,10 +169,150 @@ struct Lens {
     focal_length: usize,
 }
 
+/// One box per bucket of the hasher, each holding its lenses in the order they were put in
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct LensLibrary<H: LabelHasher = Holiday> {
+    hasher: H,
+    boxes: Vec<Vec<Lens>>,
+}
+
+/// What a step changed, to be able to undo it: the box it touched with its content from before the step
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Change {
+    box_number: usize,
+    before: Vec<Lens>,
+}
+
+impl LensLibrary {
+    /// The 256 boxes of the puzzle
+    fn new() -> Self {
+        LensLibrary::with_hasher(Holiday::standard())
+    }
+
+    fn replay(steps: &[Step]) -> Self {
+        LensLibrary::replay_with(Holiday::standard(), steps)
+    }
+}
+
+impl<H: LabelHasher> LensLibrary<H> {
+    fn with_hasher(hasher: H) -> Self {
+        let boxes = vec![vec![]; hasher.buckets()];
+        LensLibrary { hasher, boxes }
+    }
+
+    fn replay_with(hasher: H, steps: &[Step]) -> Self {
+        let mut library = LensLibrary::with_hasher(hasher);
+        steps.iter().for_each(|step| { library.apply(step); });
+        library
+    }
+
+    /// Puts the lens at the back of its box. Returns `false` and leaves the box alone if the label is already there.
+    fn insert(&mut self, label: &str, focal_length: usize) -> bool {
+        if self.get(label).is_some() {
+            return false;
+        }
+        self.boxes[self.hasher.hash(label)].push(Lens { label: label.to_string(), focal_length });
+        true
+    }
+
+    /// Replaces the focal length of the lens with this label, or inserts it. Returns the previous focal length, if any.
+    fn upsert(&mut self, label: &str, focal_length: usize) -> Option<usize> {
+        match self.boxes[self.hasher.hash(label)].iter_mut().find(|lens| lens.label == label) {
+            Some(lens) => Some(std::mem::replace(&mut lens.focal_length, focal_length)),
+            None => {
+                self.insert(label, focal_length);
+                None
+            }
+        }
+    }
+
+    /// Takes the lens out of its box, moving the lenses behind it forward, and returns its focal length
+    fn remove(&mut self, label: &str) -> Option<usize> {
+        let content = &mut self.boxes[self.hasher.hash(label)];
+        let idx = content.iter().position(|lens| lens.label == label)?;
+        Some(content.remove(idx).focal_length)
+    }
+
+    fn get(&self, label: &str) -> Option<usize> {
+        self.boxes[self.hasher.hash(label)].iter().find(|lens| lens.label == label).map(|lens| lens.focal_length)
+    }
+
+    /// The lenses in the box, from front to back
+    fn lenses(&self, box_number: usize) -> impl Iterator<Item=&Lens> {
+        self.boxes[box_number].iter()
+    }
+
+    fn apply(&mut self, step: &Step) -> Change {
+        let box_number = self.hasher.hash(step.label());
+        let before = self.boxes[box_number].clone();
+        match step {
+            Step::Set { label, focal } => { self.upsert(label, *focal); }
+            Step::Remove { label } => { self.remove(label); }
+        }
+        Change { box_number, before }
+    }
+
+    /// Reverts a change; changes should be undone in the reverse order they were made
+    fn undo(&mut self, change: Change) {
+        self.boxes[change.box_number] = change.before;
+    }
+
+    /// The boxes whose content differs from the other library, with the content in both
+    fn diff<'a>(&'a self, other: &'a LensLibrary<H>) -> Vec<(usize, &'a [Lens], &'a [Lens])> {
+        self.boxes.iter().zip(&other.boxes).enumerate()
+            .filter(|(_, (ours, theirs))| ours != theirs)
+            .map(|(box_number, (ours, theirs))| (box_number, ours.as_slice(), theirs.as_slice()))
+            .collect()
+    }
+
+    fn focusing_power(&self) -> usize {
+        self.boxes.iter().enumerate().map(|(box_number, content)| {
+            (box_number + 1) * content.iter().enumerate().map(|(idx, lens)| (idx + 1) * lens.focal_length).sum::<usize>()
+        }).sum()
+    }
+}
+
+
+/// How well a hasher spreads the labels of a step sequence over the boxes
+#[derive(Debug, Eq, PartialEq)]
+struct Analysis {
+    /// For every number of lenses, how many boxes hold that many at the end
+    histogram: BTreeMap<usize, usize>,
+    /// The most lenses any box held at any point
+    peak_occupancy: usize,
+    /// For every box that different labels were hashed to, those labels in order of first appearance
+    collisions: BTreeMap<usize, Vec<String>>,
+}
+
+fn analyse<H: LabelHasher>(hasher: H, steps: &[Step]) -> Analysis {
+    let mut labels: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    let mut library = LensLibrary::with_hasher(hasher);
+    let mut peak_occupancy = 0;
+    for step in steps {
+        let change = library.apply(step);
+        let chain = labels.entry(change.box_number).or_default();
+        if !chain.iter().any(|label| label == step.label()) {
+            chain.push(step.label().to_string());
+        }
+        peak_occupancy = peak_occupancy.max(library.boxes[change.box_number].len());
+    }
+
+    let histogram = library.boxes.iter().fold(BTreeMap::new(), |mut acc, content| {
+        *acc.entry(content.len()).or_insert(0) += 1;
+        acc
+    });
+    let collisions = labels.into_iter().filter(|(_, chain)| chain.len() > 1).collect();
+    Analysis { histogram, peak_occupancy, collisions }
+}
+
 
 #[cfg(test)]
 mod tests {
-    use crate::day15::{part1, part2};
+    use std::collections::BTreeMap;
+
+    use crate::day15::{analyse, Fnv1, Fnv1a, hash, Holiday, LabelHasher, Lens, LensLibrary, parse, part1, part2, Step};
+
+    const EXAMPLE: &str = "rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7";
 
     #[test]
     fn part_1_example_1() {
@@ -90,4 +331,107 @@ mod tests {
         let input = r"rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7";
         assert_eq!(part2(input), 145)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_parse_and_validate_steps() {
+        let steps = parse(EXAMPLE).unwrap();
+        assert_eq!(steps.len(), 11);
+        assert_eq!(steps[0], Step::Set { label: "rn".to_string(), focal: 1 });
+        assert_eq!(steps[1], Step::Remove { label: "cm".to_string() });
+        assert_eq!(steps.iter().map(|step| step.to_string()).collect::<Vec<_>>().join(","), EXAMPLE);
+
+        assert_eq!(parse("rn=1,cm=0"), Err("Step 2: Focal length in 'cm=0' should be a digit from 1 to 9".to_string()));
+        assert!(parse("rn").is_err());
+        assert!(parse("=3").is_err());
+        assert!(parse("r-n-").is_err());
+    }
+
+    #[test]
+    fn should_keep_lenses_in_order_per_box() {
+        let library = LensLibrary::replay(&parse(EXAMPLE).unwrap());
+        let labels = |box_number| library.lenses(box_number).map(|lens| lens.label.as_str()).collect::<Vec<_>>();
+        assert_eq!(labels(0), vec!["rn", "cm"]);
+        assert_eq!(labels(3), vec!["ot", "ab", "pc"]);
+        assert_eq!(library.get("ot"), Some(7));
+        assert_eq!(library.get("qp"), None);
+        assert_eq!(library.focusing_power(), 145);
+    }
+
+    #[test]
+    fn should_insert_upsert_and_remove() {
+        let mut library = LensLibrary::new();
+        assert!(library.insert("rn", 1));
+        assert!(!library.insert("rn", 5));
+        assert_eq!(library.upsert("rn", 4), Some(1));
+        assert_eq!(library.upsert("cm", 2), None);
+        assert_eq!(library.remove("rn"), Some(4));
+        assert_eq!(library.remove("rn"), None);
+        assert_eq!(library.lenses(0).collect::<Vec<_>>(), vec![&Lens { label: "cm".to_string(), focal_length: 2 }]);
+    }
+
+    #[test]
+    fn should_undo_steps_and_diff_points_in_time() {
+        let steps = parse(EXAMPLE).unwrap();
+        let mut library = LensLibrary::new();
+        let changes = steps.iter().map(|step| library.apply(step)).collect::<Vec<_>>();
+        assert_eq!(library, LensLibrary::replay(&steps));
+
+        for change in changes.into_iter().skip(5).rev() {
+            library.undo(change);
+        }
+        let after_five = LensLibrary::replay(&steps[..5]);
+        assert_eq!(library, after_five);
+
+        let at_end = LensLibrary::replay(&steps);
+        let diff = after_five.diff(&at_end);
+        assert_eq!(diff.iter().map(|(box_number, _, _)| *box_number).collect::<Vec<_>>(), vec![3]);
+        assert!(diff[0].1.is_empty());
+        assert_eq!(diff[0].2.len(), 3);
+    }
+
+    #[test]
+    fn should_support_other_hashers() {
+        assert_eq!(Holiday::new(17, 256).unwrap().hash("HASH"), hash("HASH"));
+        assert_eq!(Holiday::new(31, 1 << 20).unwrap().hash("rn"), ((114 * 31) + 110) * 31);
+        // Reference values of the 32-bit FNV hashes of "a"
+        assert_eq!(Fnv1::new(1 << 32).unwrap().hash("a"), 0x050c5d7e);
+        assert_eq!(Fnv1a::new(1 << 32).unwrap().hash("a"), 0xe40c292c);
+
+        let steps = parse(EXAMPLE).unwrap();
+        for modulus in [1, 7, 1024] {
+            let library = LensLibrary::replay_with(Fnv1a::new(modulus).unwrap(), &steps);
+            assert_eq!(library.boxes.len(), modulus);
+            assert_eq!(library.boxes.iter().map(|content| content.len()).sum::<usize>(), 5);
+            assert_eq!(library.get("ot"), Some(7));
+        }
+    }
+
+    #[test]
+    fn should_hash_with_any_multiplier_and_modulus() {
+        // A multiplier of one less than the modulus negates, so "a" hashes to -97 and "ab" to -(-97 + 98)
+        let holiday = Holiday::new(usize::MAX - 1, usize::MAX).unwrap();
+        assert_eq!(holiday.hash("a"), usize::MAX - 97);
+        assert_eq!(holiday.hash("ab"), usize::MAX - 1);
+        assert_eq!(Holiday::new(usize::MAX, 256).unwrap().hash("HASH"), Holiday::new(255, 256).unwrap().hash("HASH"));
+
+        assert!(Holiday::new(17, 0).is_err());
+        assert!(Fnv1::new(0).is_err());
+        assert!(Fnv1a::new(0).is_err());
+    }
+
+    #[test]
+    fn should_analyse_bucket_occupancy_and_collisions() {
+        let analysis = analyse(Holiday::standard(), &parse(EXAMPLE).unwrap());
+        assert_eq!(analysis.histogram, BTreeMap::from([(0, 254), (2, 1), (3, 1)]));
+        assert_eq!(analysis.peak_occupancy, 3);
+        assert_eq!(analysis.collisions, BTreeMap::from([
+            (0, vec!["rn".to_string(), "cm".to_string()]),
+            (3, vec!["pc".to_string(), "ot".to_string(), "ab".to_string()]),
+        ]));
+
+        // With a single bucket every label collides with every other
+        let analysis = analyse(Fnv1::new(1).unwrap(), &parse(EXAMPLE).unwrap());
+        assert_eq!(analysis.histogram, BTreeMap::from([(5, 1)]));
+        assert_eq!(analysis.collisions[&0].len(), 6);
+    }
+}
@@ -1,9 +1,9 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::iter::{repeat, repeat_with};
-use std::ops::{Add, Sub};
 
-use itertools::Itertools;
-use num::Num;
+use rayon::prelude::*;
 
 use crate::common::{Direction, next_coord};
 use crate::day16::Direction::{East, North, South, West};
@@ -22,18 +22,220 @@ fn part1(input: &str) -> usize {
 }
 
 fn part2(input: &str) -> usize {
-    let (field, width, height) = parse(input);
-
-    let max_energized = (0..width).flat_map(|x| vec![((x, 0), South), ((x, height - 1), North)])
-        .chain((0..height).flat_map(|y| vec![((0, y), West), ((width - 1, y), East)]))
-        .map(|(start_coord, start_direction)| {
-            determine_energization(&field, width, height, start_coord, start_direction)
-        })
+    let (field, _, _) = parse(input);
+    let max_energized = BeamGraph::new(&field).energized_from_all_entries().into_iter()
+        .map(|(_, _, energized)| energized)
         .max().unwrap();
     println!("Part 2: maximal number of tiles energized is {max_energized}");
     max_energized
 }
 
+/// Every tile on the edge with the direction pointing into the field
+fn entries(width: usize, height: usize) -> Vec<((usize, usize), Direction)> {
+    (0..width).flat_map(|x| vec![((x, 0), South), ((x, height - 1), North)])
+        .chain((0..height).flat_map(|y| vec![((0, y), East), ((width - 1, y), West)]))
+        .collect()
+}
+
+/// Brute force alternative to the `BeamGraph`: follows the light from every entry separately, in parallel
+fn energized_from_all_entries_parallel(field: &Vec<Vec<char>>) -> Vec<((usize, usize), Direction, usize)> {
+    let (width, height) = (field[0].len(), field.len());
+    entries(width, height).into_par_iter()
+        .map(|(start, direction)| (start, direction, determine_energization(field, width, height, start, direction)))
+        .collect()
+}
+
+/// A beam entering a tile while travelling in a direction
+type BeamState = ((usize, usize), Direction);
+
+/// The contraption split into segments: the path a beam follows from some tile until it hits a splitter (which is
+/// included) or leaves the field. Segments continue into the two segments starting next to the splitter, and groups of
+/// segments that feed into each other in a cycle are collapsed, so the tiles energized from a segment are only
+/// determined once and shared by all entries ending up there.
+struct BeamGraph<'a> {
+    field: &'a Vec<Vec<char>>,
+    width: usize,
+    height: usize,
+    segment_index: HashMap<BeamState, usize>,
+    segments: Vec<Segment>,
+    /// Strongly connected component of every segment
+    component_of: Vec<usize>,
+    /// For every component the tiles energized once a beam reaches it, as bitset over `y * width + x`
+    energized: Vec<Vec<u64>>,
+}
+
+struct Segment {
+    tiles: Vec<(usize, usize)>,
+    next: Vec<usize>,
+}
+
+impl<'a> BeamGraph<'a> {
+    fn new(field: &'a Vec<Vec<char>>) -> Self {
+        let mut graph = BeamGraph {
+            field,
+            width: field[0].len(),
+            height: field.len(),
+            segment_index: HashMap::new(),
+            segments: vec![],
+            component_of: vec![],
+            energized: vec![],
+        };
+
+        // Every segment not starting at an edge starts right after a splitter
+        let mut ends = vec![];
+        for y in 0..graph.height {
+            for x in 0..graph.width {
+                for direction in [North, East, South, West] {
+                    for start in graph.after_splitter(((x, y), direction)) {
+                        if !graph.segment_index.contains_key(&start) {
+                            let (tiles, end) = graph.trace(start);
+                            graph.segment_index.insert(start, graph.segments.len());
+                            graph.segments.push(Segment { tiles, next: vec![] });
+                            ends.push(end);
+                        }
+                    }
+                }
+            }
+        }
+        for (segment, end) in ends.into_iter().enumerate() {
+            graph.segments[segment].next = graph.next_segments(end);
+        }
+
+        let components = strongly_connected_components(&graph.segments);
+        graph.component_of = vec![0; graph.segments.len()];
+        for (component, members) in components.iter().enumerate() {
+            members.iter().for_each(|segment| graph.component_of[*segment] = component);
+        }
+        // Components come out in reverse topological order, so the ones they lead to are done already
+        for members in components.iter() {
+            let energized = graph.union(members.iter().flat_map(|segment| &graph.segments[*segment].next), members);
+            graph.energized.push(energized);
+        }
+        graph
+    }
+
+    /// The states right next to a splitter hit in the given state, or nothing if it isn't a splitter hit on the side
+    fn after_splitter(&self, (coord, direction): BeamState) -> Vec<BeamState> {
+        let next = next_directions(self.field, direction, coord.0, coord.1);
+        if next.len() < 2 {
+            return vec![];
+        }
+        next.into_iter()
+            .filter_map(|next| next_coord(coord, next, (self.width, self.height)).map(|start| (start, next)))
+            .collect()
+    }
+
+    fn next_segments(&self, end: Option<BeamState>) -> Vec<usize> {
+        end.map(|end| self.after_splitter(end).iter().map(|state| self.segment_index[state]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Follows the beam up to and including the next splitter, which is returned with the direction it was hit in
+    fn trace(&self, (mut coord, mut direction): BeamState) -> (Vec<(usize, usize)>, Option<BeamState>) {
+        let mut tiles = vec![];
+        let mut seen = HashSet::new();
+        while seen.insert((coord, direction)) {
+            tiles.push(coord);
+            let next = next_directions(self.field, direction, coord.0, coord.1);
+            if next.len() == 2 {
+                return (tiles, Some((coord, direction)));
+            }
+            direction = next[0];
+            match next_coord(coord, direction, (self.width, self.height)) {
+                Some(next_coord) => coord = next_coord,
+                None => break,
+            }
+        }
+        (tiles, None)
+    }
+
+    /// Bitset of the tiles of the segments together with everything energized after the `next` segments
+    fn union<'s>(&self, next: impl Iterator<Item=&'s usize>, segments: &[usize]) -> Vec<u64> {
+        let mut energized = vec![0u64; (self.width * self.height).div_ceil(64)];
+        for (x, y) in segments.iter().flat_map(|segment| &self.segments[*segment].tiles) {
+            let idx = y * self.width + x;
+            energized[idx / 64] |= 1 << (idx % 64);
+        }
+        for segment in next {
+            // Segments within the same component are not done yet, but all of their tiles are already included
+            if let Some(after) = self.energized.get(self.component_of[*segment]) {
+                energized.iter_mut().zip(after).for_each(|(word, after)| *word |= after);
+            }
+        }
+        energized
+    }
+
+    fn energized_from(&self, start: (usize, usize), direction: Direction) -> usize {
+        let (tiles, end) = self.trace((start, direction));
+        let next = self.next_segments(end);
+        let mut energized = self.union(next.iter(), &[]);
+        for (x, y) in tiles {
+            let idx = y * self.width + x;
+            energized[idx / 64] |= 1 << (idx % 64);
+        }
+        energized.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    fn energized_from_all_entries(&self) -> Vec<((usize, usize), Direction, usize)> {
+        entries(self.width, self.height).into_iter()
+            .map(|(start, direction)| (start, direction, self.energized_from(start, direction)))
+            .collect()
+    }
+}
+
+/// Tarjan's algorithm on the segment graph, without recursion since the graph can get deep. Returns the components in
+/// reverse topological order.
+fn strongly_connected_components(segments: &[Segment]) -> Vec<Vec<usize>> {
+    let mut index = vec![usize::MAX; segments.len()];
+    let mut low_link = vec![0; segments.len()];
+    let mut on_stack = vec![false; segments.len()];
+    let mut stack = vec![];
+    let mut components = vec![];
+    let mut next_index = 0;
+
+    for root in 0..segments.len() {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        // Pairs of a segment and how many of its successors have been visited
+        let mut call_stack = vec![(root, 0)];
+        while let Some((segment, child)) = call_stack.pop() {
+            if child == 0 {
+                index[segment] = next_index;
+                low_link[segment] = next_index;
+                next_index += 1;
+                stack.push(segment);
+                on_stack[segment] = true;
+            } else {
+                let previous = segments[segment].next[child - 1];
+                if on_stack[previous] {
+                    low_link[segment] = low_link[segment].min(low_link[previous]);
+                }
+            }
+
+            if let Some(&next) = segments[segment].next.get(child) {
+                call_stack.push((segment, child + 1));
+                if index[next] == usize::MAX {
+                    call_stack.push((next, 0));
+                } else if on_stack[next] {
+                    low_link[segment] = low_link[segment].min(index[next]);
+                }
+            } else if low_link[segment] == index[segment] {
+                let mut component = vec![];
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == segment {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
 fn determine_energization(field: &Vec<Vec<char>>, width: usize, height: usize, start_coord: (usize, usize), start_direction: Direction) -> usize {
     // For each tile, store if and how we entered it by representing the direction as 4-bit number
     // If we entered a tile from multiple direction we can simply XOR the numbers
@@ -43,6 +245,141 @@ fn determine_energization(field: &Vec<Vec<char>>, width: usize, height: usize, s
     visited.iter().map(|row| row.iter().filter(|&b| *b != 0).count()).sum()
 }
 
+/// A change to a single tile of the field
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Edit {
+    /// Puts a mirror or splitter on an empty tile
+    Place { coord: (usize, usize), optic: char },
+    /// Makes the tile empty
+    Remove { coord: (usize, usize) },
+    /// Turns the mirror or splitter by 90 degrees
+    Rotate { coord: (usize, usize) },
+}
+
+const OPTICS: [char; 4] = ['/', '\\', '-', '|'];
+
+fn apply_edit(field: &mut [Vec<char>], edit: &Edit) -> Result<(), String> {
+    let (Edit::Place { coord: (x, y), .. } | Edit::Remove { coord: (x, y) } | Edit::Rotate { coord: (x, y) }) = *edit;
+    let tile = field.get_mut(y).and_then(|row| row.get_mut(x))
+        .ok_or(format!("({x}, {y}) is outside of the field"))?;
+    *tile = match (edit, *tile) {
+        (Edit::Place { optic, .. }, '.') if OPTICS.contains(optic) => *optic,
+        (Edit::Place { optic, .. }, '.') => return Err(format!("'{optic}' is not a mirror or splitter")),
+        (Edit::Remove { .. } | Edit::Rotate { .. }, '.') | (Edit::Place { .. }, _) =>
+            return Err(format!("Can't apply {edit:?} to '{tile}'", tile = *tile)),
+        (Edit::Remove { .. }, _) => '.',
+        (Edit::Rotate { .. }, '/') => '\\',
+        (Edit::Rotate { .. }, '\\') => '/',
+        (Edit::Rotate { .. }, '-') => '|',
+        (Edit::Rotate { .. }, '|') => '-',
+        (Edit::Rotate { .. }, other) => return Err(format!("Can't rotate '{other}'")),
+    };
+    Ok(())
+}
+
+/// All edits that can be applied to the tile
+fn possible_edits(field: &[Vec<char>], coord: (usize, usize)) -> Vec<Edit> {
+    if field[coord.1][coord.0] == '.' {
+        OPTICS.iter().map(|optic| Edit::Place { coord, optic: *optic }).collect()
+    } else {
+        vec![Edit::Remove { coord }, Edit::Rotate { coord }]
+    }
+}
+
+/// Tries every single-tile edit and returns the one energizing the most tiles from the given entry, with that number.
+/// Only tiles the light reaches without edits are tried, since changing any other tile makes no difference.
+fn best_single_edit(field: &Vec<Vec<char>>, start: (usize, usize), direction: Direction) -> Option<(Edit, usize)> {
+    let (width, height) = (field[0].len(), field.len());
+    let edits = trace_beams(field, start, direction).order.iter()
+        .flat_map(|coord| possible_edits(field, *coord))
+        .collect::<Vec<_>>();
+    edits.par_iter().enumerate()
+        .map(|(idx, edit)| {
+            let mut edited = field.clone();
+            apply_edit(&mut edited, edit).unwrap();
+            (idx, *edit, determine_energization(&edited, width, height, start, direction))
+        })
+        // Prefer the edit on the tile the light reaches first when several are equally good
+        .max_by_key(|(idx, _, energized)| (*energized, Reverse(*idx)))
+        .map(|(_, edit, energized)| (edit, energized))
+}
+
+/// Everything the light does after entering at a tile
+struct BeamTrace {
+    /// For every tile the directions beams travelled in when entering it, as a 4-bit mask of `Direction::as_power_of_2`
+    directions: Vec<Vec<usize>>,
+    /// The tiles in the order they were first energized
+    order: Vec<(usize, usize)>,
+}
+
+fn trace_beams(field: &Vec<Vec<char>>, start: (usize, usize), direction: Direction) -> BeamTrace {
+    let (width, height) = (field[0].len(), field.len());
+    let mut directions = vec![vec![0; width]; height];
+    let order = follow_light(field, (width, height), &mut directions, start, direction);
+    BeamTrace { directions, order }
+}
+
+fn directions_in(mask: usize) -> Vec<Direction> {
+    [North, East, South, West].into_iter().filter(|direction| mask & direction.as_power_of_2() != 0).collect()
+}
+
+/// Draws the field with an arrow on every empty tile a beam passed in one direction, a double arrow where beams went
+/// both ways and a crossing where beams went both horizontally and vertically
+fn render_beams(field: &[Vec<char>], trace: &BeamTrace) -> String {
+    field.iter().zip(&trace.directions).map(|(row, directions)| {
+        row.iter().zip(directions).map(|(tile, mask)| {
+            let directions = directions_in(*mask);
+            let vertical = directions.iter().any(|direction| matches!(direction, North | South));
+            let horizontal = directions.iter().any(|direction| matches!(direction, East | West));
+            match (*tile, directions.as_slice()) {
+                ('.', []) => '.',
+                ('.', [North]) => '↑',
+                ('.', [East]) => '→',
+                ('.', [South]) => '↓',
+                ('.', [West]) => '←',
+                ('.', _) if vertical && horizontal => '┼',
+                ('.', _) if vertical => '↕',
+                ('.', _) => '↔',
+                (tile, _) => tile,
+            }
+        }).chain(['\n']).collect::<String>()
+    }).collect()
+}
+
+/// Draws the beams as a binary PPM image with `scale` x `scale` pixels per tile: mirrors and splitters in grey, and a
+/// yellow line through the middle of a tile along every axis a beam travelled on
+fn render_beams_ppm(field: &[Vec<char>], trace: &BeamTrace, scale: usize) -> Vec<u8> {
+    const BACKGROUND: [u8; 3] = [16, 16, 32];
+    const OPTICS: [u8; 3] = [160, 160, 160];
+    const BEAM: [u8; 3] = [255, 210, 0];
+
+    let (width, height) = (field[0].len(), field.len());
+    let mut image = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+    let middle = scale / 2;
+    for (row, directions) in field.iter().zip(&trace.directions) {
+        for py in 0..scale {
+            for (tile, mask) in row.iter().zip(directions) {
+                let directions = directions_in(*mask);
+                let vertical = directions.iter().any(|direction| matches!(direction, North | South));
+                let horizontal = directions.iter().any(|direction| matches!(direction, East | West));
+                for px in 0..scale {
+                    let on_optics = match tile {
+                        '/' => px + py == scale - 1,
+                        '\\' => px == py,
+                        '-' => py == middle,
+                        '|' => px == middle,
+                        _ => false,
+                    };
+                    let on_beam = (horizontal && py == middle) || (vertical && px == middle);
+                    let color = if on_optics { OPTICS } else if on_beam { BEAM } else { BACKGROUND };
+                    image.extend_from_slice(&color);
+                }
+            }
+        }
+    }
+    image
+}
+
 fn print_field(field: &Vec<Vec<char>>, width: usize, height: usize, visited: &mut Vec<Vec<usize>>, show_field: bool) {
     for y in 0..height {
         for x in 0..width {
@@ -67,32 +404,39 @@ fn parse(input: &str) -> (Vec<Vec<char>>, usize, usize) {
     (field, width, height)
 }
 
-fn follow_light(field: &Vec<Vec<char>>, (width, height): (usize, usize), visited: &mut Vec<Vec<usize>>, start: (usize, usize), incoming_dir: Direction) {
+/// Returns the tiles in the order they were first energized, where the beam moves one tile further at each step
+fn follow_light(field: &Vec<Vec<char>>, (width, height): (usize, usize), visited: &mut Vec<Vec<usize>>, start: (usize, usize), incoming_dir: Direction) -> Vec<(usize, usize)> {
     let next = next_directions(&field, incoming_dir, start.0, start.1);
     visited[start.1][start.0] = incoming_dir.as_power_of_2();
+    let mut order = vec![start];
 
     // Initially below was implemented recursively, but that gave a stack overflow. So we just create
-    // our own stack! Might contain duplicates, but that doesn't matter really...
-    let mut argument_stack: Vec<((usize, usize), Direction)> =
-        next.iter().map(|&next_dir| (start, next_dir)).collect::<Vec<_>>();
+    // our own queue! Might contain duplicates, but that doesn't matter really... Taking the oldest entry
+    // first makes all beams advance at the same pace.
+    let mut argument_queue: VecDeque<((usize, usize), Direction)> =
+        next.iter().map(|&next_dir| (start, next_dir)).collect();
 
-    while let Some((current, direction)) = argument_stack.pop() {
+    while let Some((current, direction)) = argument_queue.pop_front() {
         if let Some((next_x, next_y)) = next_coord(current, direction, (width, height)) {
             let next = visited[next_y][next_x];
             if (next & direction.as_power_of_2()) != 0 {  // Bitwise AND to check if we came this way already
                 continue;
             } else {
+                if next == 0 {
+                    order.push((next_x, next_y));
+                }
                 visited[next_y][next_x] = next ^ (direction.as_power_of_2());
             }
 
             let next_directions = next_directions(field, direction, next_x, next_y);
             for next_direction in next_directions {
-                argument_stack.push(((next_x, next_y), next_direction))
+                argument_queue.push_back(((next_x, next_y), next_direction))
             }
         } else {
             continue;  // out of bounds
         }
     }
+    order
 }
 
 fn next_directions(field: &Vec<Vec<char>>, direction: Direction, next_x: usize, next_y: usize) -> Vec<Direction> {
@@ -126,7 +470,8 @@ fn next_directions(field: &Vec<Vec<char>>, direction: Direction, next_x: usize,
 #[cfg(test)]
 mod tests {
     use std::fs;
-    use crate::day16::{part1, part2};
+    use crate::common::Direction::{East, South, West};
+    use crate::day16::{apply_edit, best_single_edit, determine_energization, Edit, possible_edits, BeamGraph, energized_from_all_entries_parallel, parse, part1, part2, render_beams, render_beams_ppm, trace_beams};
 
     #[test]
     fn part_1_example() {
@@ -184,4 +529,132 @@ mod tests {
 ..//.|....";
         assert_eq!(part2(input), 51)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn part_2_should_enter_left_and_right_edges_heading_into_the_field() {
+        // Only a beam running along a row crosses more than the two tiles of a column
+        let input = r".....
+.....";
+        assert_eq!(part2(input), 5);
+        let (field, _, _) = parse(input);
+        let energized = BeamGraph::new(&field).energized_from_all_entries();
+        assert!(energized.contains(&((0, 0), East, 5)));
+        assert!(energized.contains(&((4, 1), West, 5)));
+    }
+
+    #[test]
+    fn beam_graph_should_match_following_the_light_from_every_entry() {
+        let inputs = [
+            r".|...\....
+|.-.\.....
+.....|-...
+........|.
+..........
+.........\
+..../.\\..
+.-.-/..|..
+.|....-|.\
+..//.|....".to_string(),
+            // Splitters feeding each other in a loop
+            r"..\.-./
+..|...|
+..\.-./".to_string(),
+            fs::read_to_string("./inputs/day16/input.txt").unwrap(),
+        ];
+        for input in inputs {
+            let (field, _, _) = parse(&input);
+            assert_eq!(BeamGraph::new(&field).energized_from_all_entries(), energized_from_all_entries_parallel(&field));
+        }
+    }
+
+    #[test]
+    fn should_render_beam_directions() {
+        let (field, _, _) = parse(r"
+..|..
+.....
+..-..");
+        let trace = trace_beams(&field, (0, 0), East);
+        assert_eq!(render_beams(&field, &trace), "→→|..\n..↓..\n←←-→→\n");
+
+        let (field, _, _) = parse(r"
+.....
+....\
+..\./");
+        let trace = trace_beams(&field, (0, 1), East);
+        assert_eq!(render_beams(&field, &trace), "..↑..\n→→┼→\\\n..\\←/\n");
+    }
+
+    #[test]
+    fn should_record_order_of_first_energization() {
+        let (field, _, _) = parse(r"
+..|..
+.....
+..-..");
+        let trace = trace_beams(&field, (2, 0), South);
+        assert_eq!(trace.order, vec![(2, 0), (2, 1), (2, 2), (1, 2), (3, 2), (0, 2), (4, 2)]);
+        assert_eq!(trace.order.len(), trace.directions.iter().flatten().filter(|mask| **mask != 0).count());
+    }
+
+    #[test]
+    fn should_render_beams_to_image() {
+        let (field, _, _) = parse(r"
+..|..
+.....
+..-..");
+        let image = render_beams_ppm(&field, &trace_beams(&field, (0, 0), East), 3);
+        let header = b"P6\n15 9\n255\n";
+        assert_eq!(&image[..header.len()], header);
+        assert_eq!(image.len(), header.len() + 15 * 9 * 3);
+        let pixel = |x: usize, y: usize| &image[header.len() + 3 * (y * 15 + x)..header.len() + 3 * (y * 15 + x + 1)];
+        assert_eq!(pixel(1, 1), [255, 210, 0]);
+        assert_eq!(pixel(1, 0), [16, 16, 32]);
+        assert_eq!(pixel(7, 0), [160, 160, 160]);
+    }
+
+    #[test]
+    fn should_apply_edits() {
+        let (mut field, _, _) = parse(r"
+./
+-.");
+        apply_edit(&mut field, &Edit::Place { coord: (0, 0), optic: '|' }).unwrap();
+        apply_edit(&mut field, &Edit::Rotate { coord: (1, 0) }).unwrap();
+        apply_edit(&mut field, &Edit::Remove { coord: (0, 1) }).unwrap();
+        assert_eq!(field, vec![vec!['|', '\\'], vec!['.', '.']]);
+
+        assert!(apply_edit(&mut field, &Edit::Place { coord: (0, 0), optic: '/' }).is_err());
+        assert!(apply_edit(&mut field, &Edit::Place { coord: (0, 1), optic: 'x' }).is_err());
+        assert!(apply_edit(&mut field, &Edit::Rotate { coord: (1, 1) }).is_err());
+        assert_eq!(apply_edit(&mut field, &Edit::Remove { coord: (2, 0) }), Err("(2, 0) is outside of the field".to_string()));
+    }
+
+    #[test]
+    fn should_find_best_single_edit() {
+        let (field, width, height) = parse(r"
+.|...\....
+|.-.\.....
+.....|-...
+........|.
+..........
+.........\
+..../.\\..
+.-.-/..|..
+.|....-|.\
+..//.|....");
+        let (edit, energized) = best_single_edit(&field, (0, 0), East).unwrap();
+        let mut edited = field.clone();
+        apply_edit(&mut edited, &edit).unwrap();
+        assert_eq!(determine_energization(&edited, width, height, (0, 0), East), energized);
+
+        // Compare with trying every edit on every tile
+        let best = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|coord| possible_edits(&field, coord))
+            .map(|edit| {
+                let mut edited = field.clone();
+                apply_edit(&mut edited, &edit).unwrap();
+                determine_energization(&edited, width, height, (0, 0), East)
+            })
+            .max().unwrap();
+        assert_eq!(energized, best);
+        assert!(energized > 46);
+    }
+}
@@ -6,6 +6,7 @@ mod day05;
 mod day06;
 mod common;
 mod range_set_theory;
+mod nonogram;
 mod day07;
 mod day08;
 mod day09;
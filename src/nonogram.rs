@@ -0,0 +1,192 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+use crate::day12::valid_configs;
+
+/// A nonogram puzzle: for every row and column the sizes of the consecutive groups of filled cells, i.e. the 2-D
+/// version of the damaged springs from day 12.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    columns: Vec<Vec<usize>>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Grid(Vec<Vec<bool>>);
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum Solution {
+    Unique(Grid),
+    /// Two of the possible solutions, to show where they differ
+    Multiple(Grid, Grid),
+    None,
+}
+
+type Cells = Vec<Vec<Option<bool>>>;
+
+/// Parses the row clues, followed by an empty line and the column clues. Every clue is a line of comma-separated group
+/// sizes, where an empty line is written as `0`.
+pub fn parse(input: &str) -> Nonogram {
+    let (rows, columns) = input.trim().split_once("\n\n").expect("Should have rows and columns separated by an empty line");
+    let parse_clues = |section: &str| section.lines()
+        .map(|line| line.split(',')
+            .map(|size| size.trim().parse::<usize>().expect("Clue should consist of numbers"))
+            .filter(|size| *size > 0)
+            .collect())
+        .collect();
+    Nonogram { rows: parse_clues(rows), columns: parse_clues(columns) }
+}
+
+impl Nonogram {
+    pub fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn solve(&self) -> Solution {
+        let mut solutions = vec![];
+        self.search(vec![vec![None; self.width()]; self.height()], &mut solutions);
+        let mut solutions = solutions.into_iter();
+        match (solutions.next(), solutions.next()) {
+            (Some(a), Some(b)) => Solution::Multiple(a, b),
+            (Some(a), None) => Solution::Unique(a),
+            _ => Solution::None,
+        }
+    }
+
+    /// Line solves as far as possible, then guesses the first unknown cell and tries both options. Stops once two
+    /// solutions are found.
+    fn search(&self, cells: Cells, solutions: &mut Vec<Grid>) {
+        let Some(cells) = self.propagate(cells) else { return };
+
+        let unknown = cells.iter().enumerate()
+            .find_map(|(y, row)| row.iter().position(|cell| cell.is_none()).map(|x| (x, y)));
+        match unknown {
+            None => solutions.push(Grid(cells.iter().map(|row| row.iter().map(|cell| cell.unwrap()).collect()).collect())),
+            Some((x, y)) => {
+                for guess in [true, false] {
+                    if solutions.len() < 2 {
+                        let mut guessed = cells.clone();
+                        guessed[y][x] = Some(guess);
+                        self.search(guessed, solutions);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Repeatedly applies line solving to all rows and columns until nothing changes. Returns `None` when some line
+    /// can no longer be completed.
+    fn propagate(&self, mut cells: Cells) -> Option<Cells> {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (y, clue) in self.rows.iter().enumerate() {
+                let solved = solve_line(&cells[y], clue)?;
+                changed |= solved != cells[y];
+                cells[y] = solved;
+            }
+            for (x, clue) in self.columns.iter().enumerate() {
+                let column = cells.iter().map(|row| row[x]).collect::<Vec<_>>();
+                let solved = solve_line(&column, clue)?;
+                if solved != column {
+                    changed = true;
+                    cells.iter_mut().zip(solved).for_each(|(row, cell)| row[x] = cell);
+                }
+            }
+        }
+        Some(cells)
+    }
+}
+
+/// Fixes every unknown cell of the line that has the same value in all arrangements matching the clue, by counting the
+/// arrangements with that cell filled. Returns `None` if no arrangement matches.
+fn solve_line(line: &[Option<bool>], clue: &Vec<usize>) -> Option<Vec<Option<bool>>> {
+    let mut springs = line.iter()
+        .map(|cell| match cell {
+            Some(true) => '#',
+            Some(false) => '.',
+            None => '?',
+        })
+        .collect::<Vec<_>>();
+    let total = valid_configs(&springs.iter().collect::<String>(), clue);
+    if total == 0 {
+        return None;
+    }
+
+    Some(line.iter().enumerate().map(|(idx, cell)| {
+        cell.or_else(|| {
+            springs[idx] = '#';
+            let filled = valid_configs(&springs.iter().collect::<String>(), clue);
+            springs[idx] = '?';
+            if filled == 0 {
+                Some(false)
+            } else if filled == total {
+                Some(true)
+            } else {
+                None
+            }
+        })
+    }).collect())
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for row in self.0.iter() {
+            writeln!(f, "{}", row.iter().map(|filled| if *filled { '#' } else { '.' }).collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nonogram::{Nonogram, parse, Solution, solve_line};
+
+    #[test]
+    fn should_parse_clues() {
+        let nonogram = parse("1,1\n0\n\n2\n1");
+        assert_eq!(nonogram, Nonogram { rows: vec![vec![1, 1], vec![]], columns: vec![vec![2], vec![1]] });
+    }
+
+    #[test]
+    fn line_solving_should_fix_overlapping_cells() {
+        let solved = solve_line(&[None; 5], &vec![4]).unwrap();
+        assert_eq!(solved, vec![None, Some(true), Some(true), Some(true), None]);
+
+        let solved = solve_line(&[None, Some(true), None, None, None], &vec![1, 1]).unwrap();
+        assert_eq!(solved, vec![Some(false), Some(true), Some(false), None, None]);
+
+        assert_eq!(solve_line(&[Some(true), Some(true), None], &vec![1]), None);
+    }
+
+    #[test]
+    fn should_solve_unique_puzzle() {
+        let nonogram = parse("3\n1,1\n3\n1\n1\n\n3\n1,1\n5");
+        let Solution::Unique(grid) = nonogram.solve() else { panic!("Expected a unique solution") };
+        assert_eq!(grid.to_string(), "###\n#.#\n###\n..#\n..#\n");
+    }
+
+    #[test]
+    fn should_solve_puzzle_that_needs_guessing() {
+        // Line solving alone gets stuck on this one, but it is still unique
+        let nonogram = parse("0\n0\n2\n1,1\n\n1\n1\n1\n1");
+        let Solution::Unique(grid) = nonogram.solve() else { panic!("Expected a unique solution") };
+        assert_eq!(grid.to_string(), "....\n....\n.##.\n#..#\n");
+    }
+
+    #[test]
+    fn should_report_multiple_solutions() {
+        let Solution::Multiple(a, b) = parse("1\n1\n\n1\n1").solve() else { panic!("Expected multiple solutions") };
+        assert_eq!(a.to_string(), "#.\n.#\n");
+        assert_eq!(b.to_string(), ".#\n#.\n");
+    }
+
+    #[test]
+    fn should_report_no_solution() {
+        assert_eq!(parse("2\n0\n\n1\n0").solve(), Solution::None);
+    }
+}